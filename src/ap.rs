@@ -0,0 +1,157 @@
+//! Access to the AP tarball of a decrypted firmware.
+//!
+//! A decrypted firmware is a zip with the `AP_*.tar.md5`, `BL_*`, `CP_*` and
+//! `CSC_*` tarballs. The functions accept the zip or an extracted AP tarball.
+
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek};
+use std::path::Path;
+
+use anyhow::{anyhow, Context};
+use md5::{Digest, Md5};
+use serde::Serialize;
+
+use crate::progress::{Phase, Progress};
+use crate::Error;
+
+/// A file in the AP tarball, usually a partition image like `boot.img.lz4`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Partition {
+    pub name: String,
+    pub size: u64,
+    pub md5: String,
+}
+
+/// Calls `f` with a reader of the AP tarball.
+pub fn read<T, F>(path: &Path, f: F) -> Result<T, Error>
+where
+    F: FnOnce(&mut dyn Read) -> io::Result<T>,
+{
+    open(path, |reader, _| f(reader))
+}
+
+/// Calls `f` with a reader of the AP tarball and its size.
+fn open<T, F>(path: &Path, f: F) -> Result<T, Error>
+where
+    F: FnOnce(&mut dyn Read, u64) -> io::Result<T>,
+{
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let len = file.metadata()?.len();
+    let mut file = BufReader::new(file);
+
+    let mut magic = [0; 4];
+    file.read_exact(&mut magic)?;
+    file.rewind()?;
+
+    if magic == *b"PK\x03\x04" {
+        let mut zip = zip::ZipArchive::new(file)?;
+        let name = zip
+            .file_names()
+            .find(|name| name.starts_with("AP_"))
+            .map(str::to_owned)
+            .ok_or_else(|| anyhow!("no AP tarball in {}", path.display()))?;
+        let mut ap = zip.by_name(&name)?;
+        let len = ap.size();
+        Ok(f(&mut ap, len)?)
+    } else {
+        Ok(f(&mut file, len)?)
+    }
+}
+
+/// Lists the files of the AP tarball with their MD5 checksums.
+///
+/// The bytes read from the tarball are reported to `progress` as the extract phase.
+pub fn partitions<P: Progress>(path: &Path, progress: P) -> Result<Vec<Partition>, Error> {
+    open(path, |reader, len| {
+        progress.start(Phase::Extract, len);
+        let reader = Counting {
+            inner: reader,
+            progress: &progress,
+        };
+        let mut archive = tar::Archive::new(reader);
+        let mut partitions = Vec::new();
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let name = entry.path()?.to_string_lossy().into_owned();
+            let size = entry.size();
+
+            let mut hasher = Md5::new();
+            io::copy(&mut entry, &mut hasher)?;
+            partitions.push(Partition {
+                name,
+                size,
+                md5: format!("{:x}", hasher.finalize()),
+            });
+        }
+        progress.finish(Phase::Extract);
+        Ok(partitions)
+    })
+}
+
+struct Counting<'a, R: ?Sized, P> {
+    inner: &'a mut R,
+    progress: &'a P,
+}
+
+impl<R: Read + ?Sized, P: Progress> Read for Counting<'_, R, P> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.progress.inc(n as u64);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::TempPath;
+
+    /// Writes a tarball with the files to the temp directory, it's removed when the
+    /// returned path is dropped.
+    fn write_tar(name: &str, files: &[(&str, &[u8])]) -> TempPath {
+        let file = tempfile::Builder::new()
+            .prefix(&format!("frigg-{name}-"))
+            .suffix(".tar")
+            .tempfile()
+            .unwrap();
+        let (file, path) = file.into_parts();
+        let mut builder = tar::Builder::new(file);
+        for (name, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_cksum();
+            builder.append_data(&mut header, name, *content).unwrap();
+        }
+        builder.finish().unwrap();
+        path
+    }
+
+    #[test]
+    fn list_partitions() {
+        let path = write_tar(
+            "partitions",
+            &[("boot.img.lz4", b"boot"), ("vbmeta.img", b"")],
+        );
+
+        let partitions = partitions(&path, |_: u64| {}).unwrap();
+        assert_eq!(
+            partitions,
+            [
+                Partition {
+                    name: "boot.img.lz4".to_owned(),
+                    size: 4,
+                    md5: "881cc4157ed641a365a86452f27ed745".to_owned(),
+                },
+                Partition {
+                    name: "vbmeta.img".to_owned(),
+                    size: 0,
+                    md5: "d41d8cd98f00b204e9800998ecf8427e".to_owned(),
+                },
+            ]
+        );
+    }
+}
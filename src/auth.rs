@@ -2,7 +2,7 @@ use reqwest::header::HeaderValue;
 
 use crate::Error;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Nonce {
     pub encoded: String,
     pub value: String,
@@ -1,14 +1,33 @@
+use std::collections::BTreeMap;
 use std::path::Path;
 
 use anyhow::anyhow;
 use generic_array::{typenum::U16, GenericArray};
 use md5::{Digest, Md5};
+use serde::{Serialize, Serializer};
 
 use crate::auth::calc_logic_check;
-use crate::xml::{self, XmlExt};
+use crate::fus::{Fields, FusMsg};
 use crate::Error;
 
-#[derive(Debug)]
+/// The fields of the `Put` element that are parsed into [`BinaryInfo`].
+const KNOWN_FIELDS: &[&str] = &[
+    "BINARY_NAME",
+    "BINARY_BYTE_SIZE",
+    "BINARY_CRC",
+    "BINARY_OS_VERSION",
+    "BINARY_RELEASE_DATE",
+    "BINARY_UPDATE_DATE",
+    "CURRENT_OS_VERSION",
+    "DESCRIPTION",
+    "DEVICE_MODEL_DISPLAYNAME",
+    "DEVICE_PLATFORM",
+    "LOGIC_VALUE_FACTORY",
+    "LOGIC_VALUE_HOME",
+    "MODEL_PATH",
+];
+
+#[derive(Debug, Serialize)]
 pub struct BinaryInfo {
     pub display_name: String,
     pub os_version: String,
@@ -17,6 +36,17 @@ pub struct BinaryInfo {
     pub binary_size: u64,
     pub version: String,
     pub decrypt_key: DecryptKey,
+    pub crc: Option<String>,
+    pub binary_os_version: Option<String>,
+    pub platform: Option<String>,
+    pub release_date: Option<String>,
+    pub update_date: Option<String>,
+    pub logic_value_factory: Option<String>,
+    pub logic_value_home: Option<String>,
+    /// URL of the changelog page.
+    pub description_url: Option<String>,
+    /// All other fields of the response that aren't known (yet).
+    pub extra: BTreeMap<String, String>,
 }
 
 #[derive(Debug)]
@@ -26,32 +56,39 @@ pub enum DecryptKey {
     Unknown,
 }
 
+impl Serialize for DecryptKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::V2(key) | Self::V4(key) => serializer.serialize_str(&format!("{key:02X}")),
+            Self::Unknown => serializer.serialize_none(),
+        }
+    }
+}
+
 pub fn from_xml(model: &str, region: &str, xml: &str) -> Result<BinaryInfo, Error> {
-    let doc = xml::parse(xml)?;
+    let msg = FusMsg::<Fields>::from_xml(xml)?;
+    from_msg(model, region, &msg)
+}
 
-    let fields = doc
-        .get_elem(&["FUSMsg", "FUSBody", "Put"])
-        .ok_or(anyhow!("Missing element FUSMsg/FUSBody/Put"))?;
+pub fn from_msg(model: &str, region: &str, msg: &FusMsg) -> Result<BinaryInfo, Error> {
+    let fields = &msg.body.put;
+    let field = |name: &str| {
+        fields
+            .get(name)
+            .ok_or_else(|| anyhow!("Missing element FUSMsg/FUSBody/Put/{name}/Data"))
+    };
 
-    let binary_name = fields
-        .get_elem_text(&["BINARY_NAME", "Data"])
-        .ok_or(anyhow!(
-            "Missing element FUSMsg/FUSBody/Put/BINARY_NAME/Data"
-        ))?
-        .to_owned();
-    let binary_size = fields
-        .get_elem_text(&["BINARY_BYTE_SIZE", "Data"])
-        .ok_or(anyhow!(
-            "Missing element FUSMsg/FUSBody/Put/BINARY_NAME/Data"
-        ))?
-        .parse()?;
+    let binary_name = field("BINARY_NAME")?.to_owned();
+    let binary_size = field("BINARY_BYTE_SIZE")?.parse()?;
 
-    let version = doc
-        .get_elem_text(&["FUSMsg", "FUSBody", "Results", "LATEST_FW_VERSION", "Data"])
+    let version = msg
+        .body
+        .results
+        .as_ref()
+        .and_then(|r| r.latest_fw_version.clone())
         .ok_or(anyhow!(
             "Missing element FUSMsg/FUSBody/Results/LATEST_FW_VERSION/Data"
-        ))?
-        .to_owned();
+        ))?;
 
     let decrypt_key = match Path::new(&binary_name).extension() {
         Some(ext) if ext.eq_ignore_ascii_case("enc2") => {
@@ -60,11 +97,7 @@ pub fn from_xml(model: &str, region: &str, xml: &str) -> Result<BinaryInfo, Erro
             DecryptKey::V2(key)
         }
         Some(ext) if ext.eq_ignore_ascii_case("enc4") => {
-            let logic_value_factory = fields
-                .get_elem_text(&["LOGIC_VALUE_FACTORY", "Data"])
-                .ok_or(anyhow!(
-                    "Missing element FUSMsg/FUSBody/Put/LOGIC_VALUE_FACTORY/Data"
-                ))?;
+            let logic_value_factory = field("LOGIC_VALUE_FACTORY")?;
 
             if logic_value_factory.is_empty() {
                 tracing::warn!("logic value is empty");
@@ -76,26 +109,21 @@ pub fn from_xml(model: &str, region: &str, xml: &str) -> Result<BinaryInfo, Erro
         Some(_) | None => DecryptKey::Unknown,
     };
 
-    let display_name = fields
-        .get_elem_text(&["DEVICE_MODEL_DISPLAYNAME", "Data"])
-        .ok_or(anyhow!(
-            "Missing element FUSMsg/FUSBody/Put/DEVICE_MODEL_DISPLAYNAME/Data"
-        ))?
-        .to_owned();
-
-    let os_version = fields
-        .get_elem_text(&["CURRENT_OS_VERSION", "Data"])
-        .ok_or(anyhow!(
-            "Missing element FUSMsg/FUSBody/Put/CURRENT_OS_VERSION/Data"
-        ))?
-        .to_owned();
+    let display_name = field("DEVICE_MODEL_DISPLAYNAME")?.to_owned();
+    let os_version = field("CURRENT_OS_VERSION")?.to_owned();
+    let model_path = field("MODEL_PATH")?.to_owned();
 
-    let model_path = fields
-        .get_elem_text(&["MODEL_PATH", "Data"])
-        .ok_or(anyhow!(
-            "Missing element FUSMsg/FUSBody/Put/MODEL_PATH/Data"
-        ))?
-        .to_owned();
+    let optional = |name: &str| {
+        fields
+            .get(name)
+            .filter(|v| !v.is_empty())
+            .map(str::to_owned)
+    };
+    let extra = fields
+        .iter()
+        .filter(|(name, _)| !KNOWN_FIELDS.contains(name))
+        .map(|(name, value)| (name.to_owned(), value.to_owned()))
+        .collect();
 
     let info = BinaryInfo {
         display_name,
@@ -105,6 +133,59 @@ pub fn from_xml(model: &str, region: &str, xml: &str) -> Result<BinaryInfo, Erro
         binary_size,
         version,
         decrypt_key,
+        crc: optional("BINARY_CRC"),
+        binary_os_version: optional("BINARY_OS_VERSION"),
+        platform: optional("DEVICE_PLATFORM"),
+        release_date: optional("BINARY_RELEASE_DATE"),
+        update_date: optional("BINARY_UPDATE_DATE"),
+        logic_value_factory: optional("LOGIC_VALUE_FACTORY"),
+        logic_value_home: optional("LOGIC_VALUE_HOME"),
+        description_url: optional("DESCRIPTION"),
+        extra,
     };
     Ok(info)
 }
+
+impl BinaryInfo {
+    /// Returns the PDA, CSC and phone (modem) parts of the version.
+    pub fn version_parts(&self) -> (&str, Option<&str>, Option<&str>) {
+        let mut parts = self.version.split('/');
+        let pda = parts.next().unwrap_or_default();
+        let csc = parts.next().filter(|s| !s.is_empty());
+        let phone = parts.next().filter(|s| !s.is_empty());
+        (pda, csc, phone)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::fus::tests::BINARY_INFORM_XML;
+
+    #[test]
+    fn parse_all_fields() {
+        let info = from_xml("SM-G991B", "EUX", BINARY_INFORM_XML).unwrap();
+        assert_eq!(info.crc.as_deref(), Some("3735928559"));
+        assert_eq!(info.release_date.as_deref(), Some("20220412"));
+        assert_eq!(info.update_date, None);
+        assert_eq!(info.platform.as_deref(), Some("Android"));
+        assert_eq!(info.logic_value_home, None);
+        assert_eq!(
+            info.description_url.as_deref(),
+            Some("http://doc.samsungmobile.com/SM-G991B/EUX/doc.html")
+        );
+        assert_eq!(
+            info.version_parts(),
+            (
+                "G991BXXU5CVDD",
+                Some("G991BOXM5CVDD"),
+                Some("G991BXXU5CVDD")
+            )
+        );
+        assert_eq!(
+            info.extra.into_iter().collect::<Vec<_>>(),
+            [("SOME_NEW_FIELD".to_owned(), "x & y".to_owned())]
+        );
+    }
+}
@@ -0,0 +1,208 @@
+//! Synchronous versions of the decryptor and the client.
+
+use std::cmp;
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+
+use bytes::{Buf, Bytes};
+use tokio::runtime::{self, Runtime};
+
+use crate::auth::Nonce;
+use crate::binary_info::BinaryInfo;
+use crate::client;
+use crate::decrypt::{self, DecryptBuf};
+use crate::profile::Profile;
+use crate::Error;
+
+/// Decrypts the reader into the writer and returns the number of decrypted bytes.
+pub fn decrypt<R, W>(key: &[u8], reader: R, writer: &mut W) -> Result<u64, decrypt::Error>
+where
+    R: Read,
+    W: Write + ?Sized,
+{
+    let mut reader = DecryptReader::new(key, reader)?;
+    let amt = io::copy(&mut reader, writer)?;
+    writer.flush()?;
+    Ok(amt)
+}
+
+/// The blocking version of [`crate::decrypt::DecryptReader`] for a [`std::io::Read`].
+///
+/// Each `read` blocks on the inner reader, so it can be passed to [`io::copy`] or anything
+/// else that takes a `Read`. It shares the buffer with the async reader, the padding is
+/// removed the same way.
+pub struct DecryptReader<R> {
+    inner: R,
+    buf: DecryptBuf,
+}
+
+impl<R: Read> DecryptReader<R> {
+    pub fn new(key: &[u8], inner: R) -> Result<Self, decrypt::Error> {
+        Ok(Self {
+            inner,
+            buf: DecryptBuf::new(key)?,
+        })
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for DecryptReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while !self.buf.has_output() {
+            self.buf.compact();
+            while self.buf.needs_input() {
+                match self.inner.read(self.buf.spare()) {
+                    Ok(n) => self.buf.advance(n),
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            self.buf.decrypt()?;
+        }
+        Ok(self.buf.copy_to(out))
+    }
+}
+
+/// A blocking wrapper of [`client::Client`] with its own single-threaded runtime.
+///
+/// It must not be used from within an async runtime.
+pub struct Client {
+    inner: client::Client,
+    rt: Arc<Runtime>,
+}
+
+impl Client {
+    pub fn new() -> Result<Self, Error> {
+        let rt = runtime::Builder::new_current_thread().enable_all().build()?;
+        let inner = {
+            let _guard = rt.enter();
+            client::Client::new()?
+        };
+
+        Ok(Self {
+            inner,
+            rt: Arc::new(rt),
+        })
+    }
+
+    pub fn fetch_version(&self, model: &str, region: &str) -> Result<String, Error> {
+        self.rt.block_on(self.inner.fetch_version(model, region))
+    }
+
+    pub fn generate_nonce(&self) -> Result<Nonce, Error> {
+        self.rt.block_on(self.inner.generate_nonce())
+    }
+
+    pub fn file_info(
+        &self,
+        model: &str,
+        imei: &str,
+        region: &str,
+        version: &str,
+        nonce: &mut Nonce,
+    ) -> Result<BinaryInfo, Error> {
+        self.rt
+            .block_on(self.inner.file_info(model, imei, region, version, nonce))
+    }
+
+    pub fn file_info_with(
+        &self,
+        model: &str,
+        imei: &str,
+        region: &str,
+        version: &str,
+        profile: &Profile,
+        nonce: &mut Nonce,
+    ) -> Result<BinaryInfo, Error> {
+        self.rt.block_on(
+            self.inner
+                .file_info_with(model, imei, region, version, profile, nonce),
+        )
+    }
+
+    pub fn download(&self, info: &BinaryInfo, nonce: &mut Nonce) -> Result<Download, Error> {
+        let resp = self.rt.block_on(self.inner.download(info, nonce))?;
+
+        Ok(Download {
+            resp,
+            chunk: Bytes::new(),
+            rt: Arc::clone(&self.rt),
+        })
+    }
+}
+
+/// The body of a firmware download as a blocking reader.
+pub struct Download {
+    resp: reqwest::Response,
+    chunk: Bytes,
+    rt: Arc<Runtime>,
+}
+
+impl Download {
+    pub fn content_length(&self) -> Option<u64> {
+        self.resp.content_length()
+    }
+}
+
+impl Read for Download {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while self.chunk.is_empty() {
+            match self.rt.block_on(self.resp.chunk()) {
+                Ok(Some(chunk)) => self.chunk = chunk,
+                Ok(None) => return Ok(0),
+                Err(e) => return Err(io::Error::new(io::ErrorKind::Other, e)),
+            }
+        }
+        let n = cmp::min(out.len(), self.chunk.len());
+        out[..n].copy_from_slice(&self.chunk[..n]);
+        self.chunk.advance(n);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::decrypt::tests::{encrypted, KEY};
+
+    /// Returns at most `n` bytes per read to exercise the buffering and is
+    /// interrupted before every other read.
+    struct Trickle<'a>(&'a [u8], usize, bool);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.2 = !self.2;
+            if self.2 {
+                return Err(io::ErrorKind::Interrupted.into());
+            }
+            let n = cmp::min(self.1, buf.len()).min(self.0.len());
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn decrypt_reader() {
+        for len in [0, 1, 16, 17, 65535, 65536, 65537, 200_000] {
+            let (data, encrypted) = encrypted(len);
+
+            for step in [7, 4096, usize::MAX] {
+                let mut out = Vec::new();
+                let amt = decrypt(&KEY, Trickle(&encrypted, step, false), &mut out).unwrap();
+                assert_eq!(amt, len as u64);
+                assert_eq!(out, data);
+            }
+        }
+    }
+
+    #[test]
+    fn decrypt_reader_invalid_padding() {
+        let result = decrypt(&KEY, &[0; 31][..], &mut io::sink());
+        assert!(matches!(result, Err(decrypt::Error::Unpad)));
+    }
+}
@@ -0,0 +1,182 @@
+//! Parsing of the changelog pages on doc.samsungmobile.com.
+//!
+//! Every firmware on the page is a block of `Label : value` lines followed by
+//! the notes of the release.
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Entry {
+    pub version: String,
+    pub date: Option<String>,
+    pub android_version: Option<String>,
+    pub security_patch: Option<String>,
+    pub notes: Vec<String>,
+}
+
+impl Entry {
+    /// Compares the PDA part, so that `G991BXXU5CVDD` matches
+    /// `G991BXXU5CVDD/G991BOXM5CVDD/G991BXXU5CVDD`.
+    pub fn matches_version(&self, version: &str) -> bool {
+        let pda = |v: &str| v.split('/').next().unwrap_or_default().trim().to_owned();
+        pda(&self.version).eq_ignore_ascii_case(&pda(version))
+    }
+}
+
+/// Returns the entries in the order of the page, i.e. the latest firmware first.
+pub fn parse(html: &str) -> Vec<Entry> {
+    let mut entries = Vec::<Entry>::new();
+
+    for line in text_lines(html) {
+        let labelled = line
+            .split_once(':')
+            .map(|(label, value)| (label.trim().to_ascii_lowercase(), value.trim()));
+
+        match labelled {
+            Some((label, value)) if label == "version" || label == "build number" => {
+                let version = value.split('/').map(str::trim).collect::<Vec<_>>();
+                entries.push(Entry {
+                    version: version.join("/"),
+                    ..Default::default()
+                });
+            }
+            _ if entries.is_empty() => {}
+            Some((label, value)) if label == "release date" => {
+                set(&mut entries, |e| &mut e.date, value);
+            }
+            Some((label, value)) if label == "android version" => {
+                set(&mut entries, |e| &mut e.android_version, value);
+            }
+            Some((label, value)) if label == "security patch level" => {
+                set(&mut entries, |e| &mut e.security_patch, value);
+            }
+            _ => {
+                let note = line.trim_start_matches(['•', '-', '*']).trim();
+                if let Some(entry) = entries.last_mut() {
+                    if !note.is_empty() {
+                        entry.notes.push(note.to_owned());
+                    }
+                }
+            }
+        }
+    }
+    entries
+}
+
+fn set(entries: &mut [Entry], field: fn(&mut Entry) -> &mut Option<String>, value: &str) {
+    if let Some(entry) = entries.last_mut() {
+        let field = field(entry);
+        if field.is_none() && !value.is_empty() {
+            *field = Some(value.to_owned());
+        }
+    }
+}
+
+/// Converts the page to lines of text. Block elements and `<br>` end a line,
+/// the content of `<script>` and `<style>` elements is dropped.
+fn text_lines(html: &str) -> Vec<String> {
+    const BLOCKS: &[&str] = &[
+        "br", "p", "div", "li", "ul", "ol", "tr", "td", "h1", "h2", "h3", "h4", "h5", "h6", "table",
+    ];
+
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut rest = html;
+    let mut skip_until: Option<String> = None;
+
+    while let Some(start) = rest.find('<') {
+        if skip_until.is_none() {
+            line.push_str(&rest[..start]);
+        }
+        let Some(end) = rest[start..].find('>') else {
+            break;
+        };
+        let tag = &rest[start + 1..start + end];
+        rest = &rest[start + end + 1..];
+
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        match &skip_until {
+            Some(skip) if tag.starts_with('/') && name == *skip => skip_until = None,
+            Some(_) => {}
+            None if name == "script" || name == "style" => skip_until = Some(name),
+            None if BLOCKS.contains(&name.as_str()) => {
+                lines.push(decode_entities(&line));
+                line.clear();
+            }
+            None => {}
+        }
+    }
+    if skip_until.is_none() {
+        line.push_str(rest);
+    }
+    lines.push(decode_entities(&line));
+
+    lines
+        .into_iter()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+fn decode_entities(s: &str) -> String {
+    s.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&bull;", "•")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub const CHANGELOG_HTML: &str = r#"<html><head><title>Samsung Mobile</title>
+<style>.row { color: #000; }</style><script>var version = "1.0";</script></head>
+<body><div class="container">
+<div class="row">
+  <div class="col-md-3"><span>Version : </span>G991BXXU5CVDD / G991BOXM5CVDD / G991BXXU5CVDD</div>
+  <div class="col-md-3"><span>Release Date : </span>2022-04-25</div>
+  <div class="col-md-3"><span>Android version : </span>S(Android 12)</div>
+  <div class="col-md-3"><span>Security patch level : </span>2022-04-01</div>
+  <div class="col-md-12">&bull; The security of your device has been improved.<br>
+  &bull; Camera &amp; gallery stability improvements</div>
+</div>
+<div class="row">
+  <div class="col-md-3"><span>Version : </span>G991BXXU5CVC3 / G991BOXM5CVC3 / G991BXXU5CVC3</div>
+  <div class="col-md-3"><span>Release Date : </span>2022-03-14</div>
+  <div class="col-md-3"><span>Android version : </span>S(Android 12)</div>
+  <div class="col-md-3"><span>Security patch level : </span>2022-03-01</div>
+  <div class="col-md-12"><p>The security of your device has been improved.</p></div>
+</div>
+</div></body></html>"#;
+
+    #[test]
+    fn parse_changelog() {
+        let entries = parse(CHANGELOG_HTML);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(
+            entries[0],
+            Entry {
+                version: "G991BXXU5CVDD/G991BOXM5CVDD/G991BXXU5CVDD".to_owned(),
+                date: Some("2022-04-25".to_owned()),
+                android_version: Some("S(Android 12)".to_owned()),
+                security_patch: Some("2022-04-01".to_owned()),
+                notes: vec![
+                    "The security of your device has been improved.".to_owned(),
+                    "Camera & gallery stability improvements".to_owned(),
+                ],
+            }
+        );
+        assert_eq!(entries[1].security_patch.as_deref(), Some("2022-03-01"));
+        assert!(entries[1].matches_version("g991bxxu5cvc3"));
+        assert!(!entries[1].matches_version("G991BXXU5CVDD/G991BOXM5CVDD"));
+    }
+}
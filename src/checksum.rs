@@ -0,0 +1,260 @@
+use std::fmt::Write;
+use std::io::{self, Read};
+use std::sync::Arc;
+
+use md5::{Digest, Md5};
+use serde::Serialize;
+use sha1::Sha1;
+use sha2::Sha256;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc;
+use tokio::task::{self, JoinHandle};
+
+const CHUNK_SIZE: usize = 4 * 1024 * 1024;
+
+#[derive(Debug, Serialize)]
+pub struct Checksums {
+    pub md5: String,
+    pub sha1: String,
+    pub sha256: String,
+}
+
+impl Checksums {
+    /// Hashes the data with one thread per algorithm.
+    pub fn compute(data: &[u8]) -> Self {
+        let mut digests = Digests::default();
+        digests.update(data);
+        digests.finish()
+    }
+
+    /// Hashes the data of `reader` in chunks, e.g. a file that was decrypted earlier.
+    pub fn from_reader<R: Read>(mut reader: R) -> io::Result<Self> {
+        let mut digests = Digests::default();
+        let mut buf = vec![0; CHUNK_SIZE];
+        loop {
+            match reader.read(&mut buf) {
+                Ok(0) => return Ok(digests.finish()),
+                Ok(n) => digests.update(&buf[..n]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Hashes chunks of data one after another, each chunk with one thread per algorithm.
+///
+/// The synchronous counterpart of [`Hasher`] for data that is only borrowed, like the
+/// chunks of a memory map that are overwritten afterwards.
+#[derive(Default)]
+pub struct Digests {
+    md5: Md5,
+    sha1: Sha1,
+    sha256: Sha256,
+}
+
+impl Digests {
+    pub fn update(&mut self, data: &[u8]) {
+        let Self { md5, sha1, sha256 } = self;
+        std::thread::scope(|s| {
+            s.spawn(|| md5.update(data));
+            s.spawn(|| sha1.update(data));
+            sha256.update(data);
+        });
+    }
+
+    pub fn finish(self) -> Checksums {
+        Checksums {
+            md5: hex(&self.md5.finalize()),
+            sha1: hex(&self.sha1.finalize()),
+            sha256: hex(&self.sha256.finalize()),
+        }
+    }
+}
+
+/// Hashes a stream of chunks on the blocking thread pool with one thread per algorithm.
+///
+/// Must be created within a tokio runtime.
+pub struct Hasher {
+    senders: [mpsc::Sender<Arc<Vec<u8>>>; 3],
+    md5: JoinHandle<String>,
+    sha1: JoinHandle<String>,
+    sha256: JoinHandle<String>,
+}
+
+impl Hasher {
+    pub fn new() -> Self {
+        let (md5_tx, md5) = spawn::<Md5>();
+        let (sha1_tx, sha1) = spawn::<Sha1>();
+        let (sha256_tx, sha256) = spawn::<Sha256>();
+
+        Self {
+            senders: [md5_tx, sha1_tx, sha256_tx],
+            md5,
+            sha1,
+            sha256,
+        }
+    }
+
+    pub async fn update(&self, chunk: Arc<Vec<u8>>) {
+        for tx in &self.senders {
+            // The receivers only stop after the senders are dropped in `finish`.
+            let _ = tx.send(Arc::clone(&chunk)).await;
+        }
+    }
+
+    /// Hashes the data of `reader` without copying it, e.g. the already downloaded
+    /// part of a file.
+    pub async fn update_from<R>(&self, reader: &mut R) -> io::Result<u64>
+    where
+        R: AsyncRead + Unpin + ?Sized,
+    {
+        let mut amt = 0;
+        while let Some(chunk) = read_chunk(reader).await? {
+            amt += chunk.len() as u64;
+            self.update(Arc::new(chunk)).await;
+        }
+        Ok(amt)
+    }
+
+    pub async fn finish(self) -> Checksums {
+        drop(self.senders);
+
+        Checksums {
+            md5: join(self.md5).await,
+            sha1: join(self.sha1).await,
+            sha256: join(self.sha256).await,
+        }
+    }
+}
+
+impl Default for Hasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Copies the reader into the writer and hashes the data on the way.
+pub async fn copy<R, W>(reader: &mut R, writer: &mut W) -> io::Result<(u64, Checksums)>
+where
+    R: AsyncRead + Unpin + ?Sized,
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    copy_with(Hasher::new(), reader, writer).await
+}
+
+/// Like [`copy`], but continues with the data already passed to `hasher`.
+/// The returned size only counts the copied data.
+pub async fn copy_with<R, W>(
+    hasher: Hasher,
+    reader: &mut R,
+    writer: &mut W,
+) -> io::Result<(u64, Checksums)>
+where
+    R: AsyncRead + Unpin + ?Sized,
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    let mut amt = 0;
+    while let Some(chunk) = read_chunk(reader).await? {
+        writer.write_all(&chunk).await?;
+        amt += chunk.len() as u64;
+        hasher.update(Arc::new(chunk)).await;
+    }
+    writer.flush().await?;
+
+    Ok((amt, hasher.finish().await))
+}
+
+/// Reads up to `CHUNK_SIZE` bytes, `None` at the end of the reader.
+async fn read_chunk<R>(reader: &mut R) -> io::Result<Option<Vec<u8>>>
+where
+    R: AsyncRead + Unpin + ?Sized,
+{
+    let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+    while chunk.len() < CHUNK_SIZE {
+        let mut limited = (&mut *reader).take((CHUNK_SIZE - chunk.len()) as u64);
+        if limited.read_buf(&mut chunk).await? == 0 {
+            break;
+        }
+    }
+    Ok(Some(chunk).filter(|chunk| !chunk.is_empty()))
+}
+
+fn spawn<D>() -> (mpsc::Sender<Arc<Vec<u8>>>, JoinHandle<String>)
+where
+    D: Digest + Send + 'static,
+{
+    let (tx, mut rx) = mpsc::channel::<Arc<Vec<u8>>>(4);
+    let handle = task::spawn_blocking(move || {
+        let mut hasher = D::new();
+        while let Some(chunk) = rx.blocking_recv() {
+            hasher.update(&*chunk);
+        }
+        hex(&hasher.finalize())
+    });
+    (tx, handle)
+}
+
+async fn join(handle: JoinHandle<String>) -> String {
+    match handle.await {
+        Ok(hex) => hex,
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut s, b| {
+        let _ = write!(s, "{b:02x}");
+        s
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MD5: &str = "9e107d9d372bb6826bd81d3542a419d6";
+    const SHA1: &str = "2fd4e1c67a2d28fced849ee1bb76e7391b93eb12";
+    const SHA256: &str = "d7a8fbb307d7809469ca9abcb0082e4f8d5651e46d3cdb762d02d0bf37c9e592";
+    const DATA: &[u8] = b"The quick brown fox jumps over the lazy dog";
+
+    #[test]
+    fn compute() {
+        let sums = Checksums::compute(DATA);
+        assert_eq!(sums.md5, MD5);
+        assert_eq!(sums.sha1, SHA1);
+        assert_eq!(sums.sha256, SHA256);
+
+        let mut digests = Digests::default();
+        for chunk in DATA.chunks(10) {
+            digests.update(chunk);
+        }
+        assert_eq!(digests.finish().sha1, SHA1);
+        assert_eq!(Checksums::from_reader(DATA).unwrap().md5, MD5);
+    }
+
+    #[tokio::test]
+    async fn copy_stream() {
+        let mut out = Vec::new();
+        let (amt, sums) = copy(&mut &DATA[..], &mut out).await.unwrap();
+
+        assert_eq!(amt, DATA.len() as u64);
+        assert_eq!(out, DATA);
+        assert_eq!(sums.md5, MD5);
+        assert_eq!(sums.sha1, SHA1);
+        assert_eq!(sums.sha256, SHA256);
+    }
+
+    #[tokio::test]
+    async fn copy_resumed() {
+        let (done, rest) = DATA.split_at(16);
+        let hasher = Hasher::new();
+        assert_eq!(hasher.update_from(&mut &done[..]).await.unwrap(), 16);
+
+        let mut out = Vec::new();
+        let (amt, sums) = copy_with(hasher, &mut &rest[..], &mut out).await.unwrap();
+        assert_eq!(amt, rest.len() as u64);
+        assert_eq!(out, rest);
+        assert_eq!(sums.sha256, SHA256);
+    }
+}
@@ -1,32 +1,225 @@
+use std::fmt;
+use std::time::Duration;
+
 use anyhow::anyhow;
-use reqwest::header::AUTHORIZATION;
-use reqwest::Response;
+use reqwest::header::{AUTHORIZATION, RANGE};
+use reqwest::{Certificate, Proxy, RequestBuilder, Response, StatusCode};
 
 use crate::auth::{calc_logic_check, Nonce};
 use crate::binary_info::{self, BinaryInfo};
+use crate::changelog::{self, Entry};
+use crate::fus::{Fields, FusMsg};
+use crate::profile::Profile;
+use crate::record::{self, Recorder};
 use crate::requests;
 use crate::Error;
 
 const FOTA_BASE_URL: &str = "https://fota-cloud-dn.ospserver.net";
 const FUS_BASE_URL: &str = "https://neofussvr.sslcs.cdngc.net";
 const DOWNLOAD_BASE_URL: &str = "https://cloud-neofussvr.samsungmobile.com";
+const CHANGELOG_BASE_URL: &str = "https://doc.samsungmobile.com";
+
+/// The user agent that is sent by Samsung's own Smart Switch/Kies clients.
+pub const SMART_SWITCH_USER_AGENT: &str = "Kies2.0_FUS";
 
 pub struct Client {
     inner: reqwest::Client,
+    retry: RetryPolicy,
+    recorder: Option<Recorder>,
+    profile: Profile,
+    changelog_base_url: String,
+    download_base_url: String,
+}
+
+#[derive(Default)]
+pub struct ClientBuilder {
+    retry: RetryPolicy,
+    proxy: Option<Proxy>,
+    root_certs: Vec<Certificate>,
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    user_agent: Option<String>,
+    record: Option<record::Mode>,
+    profile: Profile,
+    changelog_base_url: Option<String>,
+    download_base_url: Option<String>,
+}
+
+/// How often and how long to wait before a failed request is sent again.
+///
+/// The delay doubles with every attempt up to `max_delay` and a random jitter
+/// of up to half the delay is subtracted.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+}
+
+/// A non-success status in the `FUSBody/Results/Status` element of a FUS response.
+#[derive(Debug)]
+pub struct FusStatus(pub u32);
+
+impl std::error::Error for FusStatus {}
+
+impl fmt::Display for FusStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FUS server responded with status {}", self.0)
+    }
+}
+
+enum Failure {
+    Transient,
+    Auth,
+    Fatal,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    fn delay(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        let delay = self
+            .initial_delay
+            .saturating_mul(factor)
+            .min(self.max_delay);
+        delay.mul_f64(1.0 - fastrand::f64() / 2.0)
+    }
+}
+
+impl ClientBuilder {
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Sends all requests through the proxy. HTTP(S) and SOCKS5 proxies are supported.
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    pub fn add_root_certificate(mut self, cert: Certificate) -> Self {
+        self.root_certs.push(cert);
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the timeout for each read, so that stalled downloads fail
+    /// without limiting the total duration of a download.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
+    /// Records all requests and responses to the FOTA and FUS servers or replays
+    /// them from a previous recording instead of sending the requests.
+    pub fn record(mut self, mode: record::Mode) -> Self {
+        self.record = Some(mode);
+        self
+    }
+
+    /// Sets the profile that is used by [`Client::file_info`].
+    pub fn profile(mut self, profile: Profile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    /// Overrides the base url of the changelog pages, e.g. to serve them locally.
+    pub fn changelog_base_url(mut self, url: impl Into<String>) -> Self {
+        self.changelog_base_url = Some(url.into());
+        self
+    }
+
+    /// Overrides the base url of the firmware downloads, e.g. to download from a local
+    /// mirror. Unlike the Samsung servers, such a server is also used by a replay.
+    pub fn download_base_url(mut self, url: impl Into<String>) -> Self {
+        self.download_base_url = Some(url.into());
+        self
+    }
+
+    pub fn build(self) -> Result<Client, Error> {
+        let mut builder = reqwest::Client::builder().cookie_store(true);
+
+        if let Some(proxy) = self.proxy {
+            builder = builder.proxy(proxy);
+        }
+        for cert in self.root_certs {
+            builder = builder.add_root_certificate(cert);
+        }
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.read_timeout {
+            builder = builder.read_timeout(timeout);
+        }
+        if let Some(user_agent) = self.user_agent {
+            builder = builder.user_agent(user_agent);
+        }
+        let client = builder.build()?;
+
+        Ok(Client {
+            inner: client,
+            retry: self.retry,
+            recorder: self.record.map(Recorder::new).transpose()?,
+            profile: self.profile,
+            changelog_base_url: self
+                .changelog_base_url
+                .unwrap_or_else(|| CHANGELOG_BASE_URL.to_owned()),
+            download_base_url: self
+                .download_base_url
+                .unwrap_or_else(|| DOWNLOAD_BASE_URL.to_owned()),
+        })
+    }
 }
 
 impl Client {
     pub fn new() -> Result<Self, Error> {
-        let client = reqwest::Client::builder()
-            .cookie_store(true)
-            .build()?;
+        Self::builder().build()
+    }
 
-        Ok(Self { inner: client })
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::default()
     }
 
     pub async fn fetch_version(&self, model: &str, region: &str) -> Result<String, Error> {
+        tracing::info!("fetching the latest version of {model} in {region}");
+        let mut attempt = 1;
+        loop {
+            match self.try_fetch_version(model, region).await {
+                Ok(version) => return Ok(version),
+                Err(e) => self.recover(&mut attempt, e, None).await?,
+            }
+        }
+    }
+
+    async fn try_fetch_version(&self, model: &str, region: &str) -> Result<String, Error> {
         let url = format!("{FOTA_BASE_URL}/firmware/{region}/{model}/version.xml");
-        let resp = self.inner.get(url).send().await?;
+        let resp = self.send("fetch_version", self.inner.get(url)).await?;
         let xml = resp.error_for_status()?.text().await?;
 
         tracing::debug!(request = "fetch_version", "{xml}");
@@ -34,14 +227,54 @@ impl Client {
         Ok(crate::version::from_xml(&xml)?)
     }
 
+    pub fn changelog_url(&self, model: &str, region: &str) -> String {
+        let base = self.changelog_base_url.trim_end_matches('/');
+        format!("{base}/{model}/{region}/doc.html")
+    }
+
+    /// Fetches the changelog page of a model and region.
+    pub async fn changelog(&self, model: &str, region: &str) -> Result<Vec<Entry>, Error> {
+        let html = self
+            .fetch_changelog(&self.changelog_url(model, region))
+            .await?;
+        Ok(changelog::parse(&html))
+    }
+
+    /// Fetches the changelog page from the `DESCRIPTION` url of a firmware.
+    pub async fn fetch_changelog(&self, url: &str) -> Result<String, Error> {
+        tracing::info!("fetching the changelog {url}");
+        let mut attempt = 1;
+        loop {
+            match self.try_fetch_changelog(url).await {
+                Ok(html) => return Ok(html),
+                Err(e) => self.recover(&mut attempt, e, None).await?,
+            }
+        }
+    }
+
+    async fn try_fetch_changelog(&self, url: &str) -> Result<String, Error> {
+        let resp = self.send("fetch_changelog", self.inner.get(url)).await?;
+        Ok(resp.error_for_status()?.text().await?)
+    }
+
     pub async fn generate_nonce(&self) -> Result<Nonce, Error> {
+        tracing::info!("starting a FUS session");
+        let mut attempt = 1;
+        loop {
+            match self.try_generate_nonce().await {
+                Ok(nonce) => return Ok(nonce),
+                Err(e) => self.recover(&mut attempt, e, None).await?,
+            }
+        }
+    }
+
+    async fn try_generate_nonce(&self) -> Result<Nonce, Error> {
         let url = format!("{FUS_BASE_URL}/NF_DownloadGenerateNonce.do");
-        let resp = self
+        let req = self
             .inner
             .get(url)
-            .header(AUTHORIZATION, r#"FUS newauth="1""#)
-            .send()
-            .await?;
+            .header(AUTHORIZATION, r#"FUS newauth="1""#);
+        let resp = self.send("generate_nonce", req).await?.error_for_status()?;
 
         let nonce = resp
             .headers()
@@ -59,10 +292,47 @@ impl Client {
         region: &str,
         version: &str,
         nonce: &mut Nonce,
+    ) -> Result<BinaryInfo, Error> {
+        self.file_info_with(model, imei, region, version, &self.profile, nonce)
+            .await
+    }
+
+    /// Like [`Client::file_info`] but with another profile, e.g. to query the
+    /// home binary instead of the factory binary.
+    pub async fn file_info_with(
+        &self,
+        model: &str,
+        imei: &str,
+        region: &str,
+        version: &str,
+        profile: &Profile,
+        nonce: &mut Nonce,
+    ) -> Result<BinaryInfo, Error> {
+        tracing::info!("fetching the file info of {version} for {model} in {region}");
+        let mut attempt = 1;
+        loop {
+            match self
+                .try_file_info(model, imei, region, version, profile, nonce)
+                .await
+            {
+                Ok(info) => return Ok(info),
+                Err(e) => self.recover(&mut attempt, e, Some(&mut *nonce)).await?,
+            }
+        }
+    }
+
+    async fn try_file_info(
+        &self,
+        model: &str,
+        imei: &str,
+        region: &str,
+        version: &str,
+        profile: &Profile,
+        nonce: &mut Nonce,
     ) -> Result<BinaryInfo, Error> {
         let check = calc_logic_check(version, &nonce.value);
 
-        let data = requests::file_info(model, imei, region, version, &check);
+        let data = requests::file_info(model, imei, region, version, &check, profile)?;
         let xml = self
             .request("NF_DownloadBinaryInform.do", data, nonce)
             .await?
@@ -72,29 +342,79 @@ impl Client {
 
         tracing::debug!(request = "file_info", "{xml}");
 
+        check_status(&xml)?;
         binary_info::from_xml(model, region, &xml)
     }
 
     pub async fn download(&self, info: &BinaryInfo, nonce: &mut Nonce) -> Result<Response, Error> {
-        self.init_download(&info.binary_name, nonce)
+        self.download_from(info, nonce, 0).await
+    }
+
+    /// Requests the firmware file starting at `offset` with a range request.
+    ///
+    /// Only the request is retried, [`crate::schedule::download_from`] resumes a body
+    /// that fails partway through with another call at the offset it reached.
+    pub async fn download_from(
+        &self,
+        info: &BinaryInfo,
+        nonce: &mut Nonce,
+        offset: u64,
+    ) -> Result<Response, Error> {
+        tracing::info!("requesting {} from byte {offset}", info.binary_name);
+        let mut attempt = 1;
+        loop {
+            match self.try_download(info, nonce, offset).await {
+                Ok(resp) => return Ok(resp),
+                Err(e) => self.recover(&mut attempt, e, Some(&mut *nonce)).await?,
+            }
+        }
+    }
+
+    async fn try_download(
+        &self,
+        info: &BinaryInfo,
+        nonce: &mut Nonce,
+        offset: u64,
+    ) -> Result<Response, Error> {
+        let xml = self
+            .init_download(&info.binary_name, nonce)
             .await?
-            .error_for_status()?;
+            .error_for_status()?
+            .text()
+            .await?;
+
+        tracing::debug!(request = "init_download", "{xml}");
+
+        check_status(&xml)?;
 
         let url = format!(
-            "{DOWNLOAD_BASE_URL}/NF_DownloadBinaryInitForMass.do?file={}{}",
-            info.model_path, info.binary_name
+            "{}/NF_DownloadBinaryInitForMass.do?file={}{}",
+            self.download_base_url.trim_end_matches('/'),
+            info.model_path,
+            info.binary_name
         );
         let auth = format!(
             r#"FUS nonce="{}", signature="{}", type="", nc="", realm="", newauth="1""#,
             nonce.encoded, nonce.signature
         );
-        let resp = self
+        match &self.recorder {
+            Some(recorder) if self.download_base_url == DOWNLOAD_BASE_URL => {
+                recorder.check_unrecorded(&url)?;
+            }
+            _ => {}
+        }
+        let mut req = self
             .inner
             .get(url)
-            .header(reqwest::header::AUTHORIZATION, auth)
-            .send()
-            .await?
-            .error_for_status()?;
+            .header(reqwest::header::AUTHORIZATION, auth);
+        if offset > 0 {
+            req = req.header(RANGE, format!("bytes={offset}-"));
+        }
+        let resp = req.send().await?.error_for_status()?;
+
+        if offset > 0 && resp.status() != StatusCode::PARTIAL_CONTENT {
+            return Err(anyhow!("server doesn't support resuming downloads"));
+        }
         Ok(resp)
     }
 
@@ -107,7 +427,7 @@ impl Client {
         let basename = &basename[basename.len() - 16..];
         let check = calc_logic_check(basename, &nonce.value);
 
-        let data = requests::init_download(filename, &check);
+        let data = requests::init_download(filename, &check)?;
 
         self.request("NF_DownloadBinaryInitForMass.do", data, nonce)
             .await
@@ -125,13 +445,12 @@ impl Client {
             r#"FUS nonce="", signature="{}", type="", nc="", realm="", newauth="1""#,
             nonce.signature
         );
-        let resp = self
+        let req = self
             .inner
             .post(url)
             .header(reqwest::header::AUTHORIZATION, auth)
-            .body(data)
-            .send()
-            .await?;
+            .body(data);
+        let resp = self.send(path, req).await?;
 
         if let Some(value) = resp.headers().get("NONCE") {
             *nonce = Nonce::try_from(value)?;
@@ -139,4 +458,149 @@ impl Client {
 
         Ok(resp)
     }
+
+    async fn send(&self, name: &str, req: RequestBuilder) -> Result<Response, Error> {
+        match &self.recorder {
+            Some(recorder) => recorder.send(&self.inner, name, req.build()?).await,
+            None => Ok(req.send().await?),
+        }
+    }
+
+    /// Waits before the next attempt of a failed request or returns the error if the
+    /// request shouldn't be retried. A new nonce is generated after auth failures.
+    pub(crate) async fn recover(
+        &self,
+        attempt: &mut u32,
+        err: Error,
+        nonce: Option<&mut Nonce>,
+    ) -> Result<(), Error> {
+        let auth = match classify(&err) {
+            Failure::Transient => false,
+            Failure::Auth if nonce.is_some() => true,
+            Failure::Auth | Failure::Fatal => return Err(err),
+        };
+        if *attempt >= self.retry.max_attempts {
+            return Err(err);
+        }
+
+        let delay = self.retry.delay(*attempt);
+        tracing::warn!(
+            "request failed: {err:#}. retrying in {delay:.1?} ({}/{})",
+            attempt,
+            self.retry.max_attempts - 1
+        );
+        tokio::time::sleep(delay).await;
+        *attempt += 1;
+
+        if let (true, Some(nonce)) = (auth, nonce) {
+            // A failed refresh shows up as another auth failure of the next attempt.
+            match self.try_generate_nonce().await {
+                Ok(new) => *nonce = new,
+                Err(e) => tracing::warn!("failed to refresh nonce: {e:#}"),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn classify(err: &Error) -> Failure {
+    if let Some(FusStatus(status)) = err.downcast_ref() {
+        return match status {
+            401 => Failure::Auth,
+            // 408 in the body means the firmware isn't available, not a timeout.
+            500..=599 => Failure::Transient,
+            _ => Failure::Fatal,
+        };
+    }
+    if let Some(err) = err.downcast_ref::<reqwest::Error>() {
+        return match err.status() {
+            Some(StatusCode::UNAUTHORIZED) => Failure::Auth,
+            Some(StatusCode::REQUEST_TIMEOUT | StatusCode::TOO_MANY_REQUESTS) => Failure::Transient,
+            Some(status) if status.is_server_error() => Failure::Transient,
+            Some(_) => Failure::Fatal,
+            None if err.is_timeout() || err.is_connect() || err.is_request() => Failure::Transient,
+            None if err.is_body() || err.is_decode() => Failure::Transient,
+            None => Failure::Fatal,
+        };
+    }
+    Failure::Fatal
+}
+
+fn check_status(xml: &str) -> Result<(), Error> {
+    match FusMsg::<Fields>::from_xml(xml)?.status() {
+        Some(200) | None => Ok(()),
+        Some(status) => Err(FusStatus(status).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_delay() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(5),
+        };
+        for (attempt, max) in [(1, 1), (2, 2), (3, 4), (4, 5), (9, 5)] {
+            let delay = policy.delay(attempt);
+            let max = Duration::from_secs(max);
+            assert!(delay <= max && delay >= max / 2, "{attempt}: {delay:?}");
+        }
+    }
+
+    #[test]
+    fn fus_status() {
+        let xml = |status| {
+            format!(
+                "<FUSMsg><FUSBody><Results><Status>{status}</Status></Results></FUSBody></FUSMsg>"
+            )
+        };
+        assert!(check_status(&xml("200")).is_ok());
+
+        let err = check_status(&xml("401")).unwrap_err();
+        assert!(matches!(classify(&err), Failure::Auth));
+
+        let err = check_status(&xml("503")).unwrap_err();
+        assert!(matches!(classify(&err), Failure::Transient));
+
+        let err = check_status(&xml("400")).unwrap_err();
+        assert!(matches!(classify(&err), Failure::Fatal));
+
+        let err = check_status(&xml("408")).unwrap_err();
+        assert!(matches!(classify(&err), Failure::Fatal));
+    }
+
+    #[tokio::test]
+    async fn changelog_base_url() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 1024];
+            let n = stream.read(&mut buf).await.unwrap();
+            let body = crate::changelog::tests::CHANGELOG_HTML;
+            let resp = format!(
+                "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            stream.write_all(resp.as_bytes()).await.unwrap();
+            String::from_utf8_lossy(&buf[..n]).into_owned()
+        });
+
+        let client = Client::builder()
+            .changelog_base_url(format!("http://{addr}/"))
+            .build()
+            .unwrap();
+        let entries = client.changelog("SM-G991B", "EUX").await.unwrap();
+        assert_eq!(entries.len(), 2);
+
+        let request = server.await.unwrap();
+        assert!(request.starts_with("GET /SM-G991B/EUX/doc.html "));
+    }
 }
@@ -1,27 +1,193 @@
 use std::any::Any;
 
 use clap::builder::ValueParser;
+use clap::value_parser;
 
-use clap::{Arg, ArgMatches, Command};
+use clap::{Arg, ArgAction, ArgMatches, Command, ValueHint};
+
+use frigg::profile::{BinaryNature, Profile};
+use frigg::regions;
 
 pub trait CommandExt {
     fn args_model_imei_region(self) -> Command;
+
+    fn args_model_region(self) -> Command;
+
+    fn args_client(self) -> Command;
+
+    fn args_profile(self) -> Command;
+
+    fn args_logging(self) -> Command;
+
+    fn args_output_policy(self) -> Command;
 }
 
 impl CommandExt for Command {
-    fn args_model_imei_region(self) -> Command {
+    fn args_client(self) -> Command {
+        let network = |arg: Arg| arg.help_heading("Network options").global(true);
+
+        self.arg(network(
+            opt("proxy", "proxy url (http, https, socks5 or socks5h)").value_name("URL"),
+        ))
+        .arg(network(
+            opt("cacert", "additional CA certificates in PEM format")
+                .value_name("FILE")
+                .value_parser(ValueParser::path_buf()),
+        ))
+        .arg(network(
+            opt("connect-timeout", "timeout for establishing connections")
+                .value_name("DURATION")
+                .value_parser(humantime::parse_duration),
+        ))
+        .arg(network(
+            opt("read-timeout", "timeout for each read of a response")
+                .value_name("DURATION")
+                .value_parser(humantime::parse_duration),
+        ))
+        .arg(network(
+            opt(
+                "user-agent",
+                "user agent or `smart-switch` for the one of Samsung's client",
+            )
+            .value_name("USER_AGENT"),
+        ))
+        .arg(network(
+            opt(
+                "retries",
+                "number of retries for failed requests [default: 3]",
+            )
+            .value_name("COUNT")
+            .value_parser(value_parser!(u32)),
+        ))
+        .arg(network(
+            opt("retry-delay", "delay before the first retry [default: 1s]")
+                .value_name("DURATION")
+                .value_parser(humantime::parse_duration),
+        ))
+        .arg(network(
+            opt("record", "record the requests and responses to a directory")
+                .value_name("DIR")
+                .value_parser(ValueParser::path_buf())
+                .conflicts_with("replay"),
+        ))
+        .arg(network(
+            opt("replay", "replay the responses from a recorded directory")
+                .value_name("DIR")
+                .value_parser(ValueParser::path_buf()),
+        ))
+    }
+
+    fn args_logging(self) -> Command {
+        let logging = |arg: Arg| arg.help_heading("Logging options");
+
+        // `-v` is taken by `--firmware-version` of some subcommands, so it has to come
+        // before the subcommand. `--verbose` and `-q` are global.
+        self.arg(logging(
+            Arg::new("v")
+                .short('v')
+                .help("log what frigg is doing, -vv for debug output")
+                .action(ArgAction::Count)
+                .conflicts_with("quiet"),
+        ))
+        .arg(logging(
+            opt("verbose", "same as -v, also accepted after the subcommand")
+                .action(ArgAction::Count)
+                .conflicts_with("quiet")
+                .global(true),
+        ))
+        .arg(logging(
+            opt("quiet", "only log errors and hide progress bars")
+                .short('q')
+                .action(ArgAction::SetTrue)
+                .global(true),
+        ))
+        .arg(logging(
+            opt("log-format", "format of the log messages")
+                .value_name("FORMAT")
+                .value_parser(["text", "json"])
+                .default_value("text")
+                .global(true),
+        ))
+        .arg(logging(
+            opt(
+                "log-file",
+                "append the log messages to a file instead of stderr",
+            )
+            .value_name("FILE")
+            .value_parser(ValueParser::path_buf())
+            .global(true),
+        ))
+        .arg(logging(
+            opt(
+                "progress",
+                "draw progress bars, print JSON events or nothing [default: bar]",
+            )
+            .value_name("MODE")
+            .value_parser(["bar", "json", "none"])
+            .global(true),
+        ))
+        .arg(logging(
+            opt("progress-fd", "write the JSON events to a file descriptor")
+                .value_name("FD")
+                .value_parser(value_parser!(i32))
+                .requires("progress")
+                .global(true),
+        ))
+    }
+
+    fn args_profile(self) -> Command {
+        let request = |arg: Arg| arg.help_heading("Request options").global(true);
+
+        self.arg(request(
+            opt("profile", "client to identify as: smart-switch or kies")
+                .value_name("PROFILE")
+                .value_parser(value_parser!(Profile)),
+        ))
+        .arg(request(
+            opt("binary-nature", "query the factory (1) or home (0) binary")
+                .value_name("NATURE")
+                .value_parser(value_parser!(BinaryNature)),
+        ))
+        .arg(request(
+            opt("fus-field", "send an additional field with the request")
+                .value_name("NAME=VALUE")
+                .value_parser(parse_field)
+                .action(ArgAction::Append),
+        ))
+    }
+
+    fn args_output_policy(self) -> Command {
         self.arg(
-            required_opt("model", "device model")
-                .short('m')
-                .value_name("MODEL"),
+            opt("overwrite", "replace an existing output file")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["skip-existing", "rename"]),
+        )
+        .arg(
+            opt("skip-existing", "do nothing if the output file exists")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("rename"),
         )
-        .arg(required_opt("imei", "IMEI/serial number").value_name("IMEI"))
         .arg(
-            required_opt("region", "region model")
-                .short('r')
-                .value_name("REGION"),
+            opt(
+                "rename",
+                "write to a new name like `file (1).zip` if the output exists",
+            )
+            .action(ArgAction::SetTrue),
         )
     }
+
+    fn args_model_region(self) -> Command {
+        self.arg(model_arg().required(true))
+            .arg(region_arg().required(true))
+            .arg(no_region_check())
+    }
+
+    fn args_model_imei_region(self) -> Command {
+        self.arg(model_arg().required(true))
+            .arg(required_opt("imei", "IMEI/serial number").value_name("IMEI"))
+            .arg(region_arg().required(true))
+            .arg(no_region_check())
+    }
 }
 
 pub trait ArgMatchesExt {
@@ -46,6 +212,47 @@ impl ArgMatchesExt for ArgMatches {
     }
 }
 
+/// `-m/--model`, completed with the models of the local catalog, see [`crate::completions`].
+pub fn model_arg() -> Arg {
+    opt("model", "device model")
+        .short('m')
+        .value_name("MODEL")
+        .value_hint(ValueHint::Other)
+}
+
+/// `-r/--region`, completed with the bundled regions.
+pub fn region_arg() -> Arg {
+    opt("region", "region model")
+        .short('r')
+        .value_name("REGION")
+        .value_hint(ValueHint::Other)
+}
+
+fn no_region_check() -> Arg {
+    opt(
+        "no-region-check",
+        "don't warn about regions that aren't in the known regions",
+    )
+    .action(ArgAction::SetTrue)
+}
+
+/// Warns about a `--region` that isn't in the bundled regions and suggests similar
+/// codes for typos. The region is queried anyway, the list isn't exhaustive.
+pub fn check_region(matches: &ArgMatches) {
+    let Ok(Some(region)) = matches.try_get_one::<String>("region") else {
+        return;
+    };
+    if matches.get_flag("no-region-check") || regions::find(region).is_some() {
+        return;
+    }
+    let hint = match regions::suggest(region).as_slice() {
+        [] => "see `frigg regions` for the known regions".to_owned(),
+        [code] => format!("did you mean `{code}`?"),
+        codes => format!("did you mean one of {}?", codes.join(", ")),
+    };
+    tracing::warn!("unknown region `{region}`, {hint}");
+}
+
 pub fn opt(name: &'static str, help: &'static str) -> Arg {
     Arg::new(name).long(name).help(help)
 }
@@ -63,3 +270,136 @@ pub fn path_arg(name: &'static str, help: &'static str) -> Arg {
 pub fn required_path_arg(name: &'static str, help: &'static str) -> Arg {
     path_arg(name, help).required(true)
 }
+
+/// A firmware given as `MODEL:REGION[:VERSION]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    pub model: String,
+    pub region: String,
+    pub version: Option<String>,
+}
+
+pub fn parse_target(s: &str) -> Result<Target, String> {
+    let mut parts = s.splitn(3, ':');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(model), Some(region), version) if !model.is_empty() && !region.is_empty() => {
+            Ok(Target {
+                model: model.to_owned(),
+                region: region.to_owned(),
+                version: version.filter(|v| !v.is_empty()).map(str::to_owned),
+            })
+        }
+        _ => Err(format!(
+            "invalid firmware `{s}`, expected MODEL:REGION[:VERSION]"
+        )),
+    }
+}
+
+/// Parses a `NAME=VALUE` field of `--fus-field`.
+fn parse_field(s: &str) -> Result<(String, String), String> {
+    let Some((name, value)) = s.split_once('=') else {
+        return Err(format!("invalid field `{s}`, expected NAME=VALUE"));
+    };
+    // The name becomes an XML element, keep it to the characters FUS uses.
+    let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+    if !valid {
+        return Err(format!("invalid field name `{name}`"));
+    }
+    Ok((name.to_owned(), value.to_owned()))
+}
+
+/// Parses a rate in bytes per second with an optional `K`, `M` or `G` suffix.
+pub fn parse_rate(s: &str) -> Result<u64, String> {
+    let (num, unit) = match s.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => (&s[..i], c.to_ascii_uppercase()),
+        _ => (s, 'B'),
+    };
+    let mult = match unit {
+        'B' => 1,
+        'K' => 1024,
+        'M' => 1024 * 1024,
+        'G' => 1024 * 1024 * 1024,
+        _ => return Err(format!("unknown unit `{unit}`, expected K, M or G")),
+    };
+    match num.parse::<f64>() {
+        Ok(n) if n * mult as f64 >= 1.0 => Ok((n * mult as f64) as u64),
+        Ok(_) => Err(format!("rate `{s}` is less than 1 byte per second")),
+        Err(_) => Err(format!("invalid rate `{s}`")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn region_values() {
+        let cmd = Command::new("frigg").arg(region_arg());
+        let matches = cmd.try_get_matches_from(["frigg", "-r", "ABC"]).unwrap();
+        assert_eq!(matches.get_region().map(String::as_str), Some("ABC"));
+    }
+
+    #[test]
+    fn global_logging_flags() {
+        let cmd = Command::new("frigg")
+            .args_logging()
+            .subcommand(Command::new("check"));
+        let matches = cmd
+            .clone()
+            .try_get_matches_from(["frigg", "check", "-q"])
+            .unwrap();
+        assert!(matches.get_flag("quiet"));
+
+        let matches = cmd
+            .try_get_matches_from(["frigg", "-v", "check", "--verbose"])
+            .unwrap();
+        assert_eq!(matches.get_count("v") + matches.get_count("verbose"), 2);
+    }
+
+    #[test]
+    fn targets() {
+        let target = parse_target("SM-G991B:EUX").unwrap();
+        assert_eq!((target.model.as_str(), target.version), ("SM-G991B", None));
+
+        let target = parse_target("SM-G991B:INS:G991BXXU5CVDD/G991BODM5CVDD").unwrap();
+        assert_eq!(target.region, "INS");
+        assert_eq!(
+            target.version.as_deref(),
+            Some("G991BXXU5CVDD/G991BODM5CVDD")
+        );
+
+        assert!(parse_target("SM-G991B").is_err());
+        assert!(parse_target(":EUX").is_err());
+    }
+
+    #[test]
+    fn fields() {
+        assert_eq!(
+            parse_field("DEVICE_CSC_CODE2=EUX"),
+            Ok(("DEVICE_CSC_CODE2".to_owned(), "EUX".to_owned()))
+        );
+        assert_eq!(parse_field("A=").unwrap().1, "");
+        assert!(parse_field("A B=1").is_err());
+        assert!(parse_field("1A=1").is_err());
+        assert!(parse_field("=1").is_err());
+        assert!(parse_field("A").is_err());
+    }
+
+    #[test]
+    fn rates() {
+        assert_eq!(parse_rate("1000"), Ok(1000));
+        assert_eq!(parse_rate("500K"), Ok(500 * 1024));
+        assert_eq!(parse_rate("5M"), Ok(5 * 1024 * 1024));
+        assert_eq!(parse_rate("1.5m"), Ok(3 * 512 * 1024));
+        assert_eq!(parse_rate("1G"), Ok(1024 * 1024 * 1024));
+        assert!(parse_rate("0").is_err());
+        assert!(parse_rate("0.5").is_err());
+        assert!(parse_rate("-1K").is_err());
+        assert_eq!(parse_rate("0.5K"), Ok(512));
+        assert!(parse_rate("5X").is_err());
+        assert!(parse_rate("M").is_err());
+    }
+}
@@ -0,0 +1,145 @@
+//! Shell completions that look up models and regions while completing.
+//!
+//! The scripts of clap_complete are static. For bash, zsh and fish, `-m/--model` and
+//! `-r/--region` are completed by the hidden `complete-values` subcommand instead, so
+//! the scripts offer newly cataloged models without being generated again.
+
+use std::io::{self, Write};
+
+use clap::Command;
+use clap_complete::Shell;
+
+use frigg::models::{self, Catalog};
+use frigg::regions;
+
+/// Prints the completions of `shell` for `cmd`.
+pub fn generate(shell: Shell, cmd: &mut Command, out: &mut dyn Write) -> io::Result<()> {
+    let name = cmd.get_name().to_owned();
+    let mut script = Vec::new();
+    clap_complete::generate(shell, cmd, &name, &mut script);
+    let script = String::from_utf8(script).expect("completions are UTF-8");
+
+    match shell {
+        Shell::Bash => {
+            out.write_all(script.as_bytes())?;
+            out.write_all(BASH.as_bytes())
+        }
+        Shell::Zsh => {
+            // clap_complete leaves the values of `ValueHint::Other` args empty as `( )`.
+            let mut script = script;
+            for (value_name, kind) in [
+                ("MODEL", "model"),
+                ("REGION", "region"),
+                ("REGION,...", "region"),
+            ] {
+                script = script.replace(
+                    &format!(":{value_name}:( )"),
+                    &format!(":{value_name}:_frigg_values {kind}"),
+                );
+            }
+            // The function has to be defined before the script calls `_frigg` the first time.
+            let end = script
+                .rfind("if [ \"$funcstack[1]\"")
+                .unwrap_or(script.len());
+            write!(out, "{}{ZSH}{}", &script[..end], &script[end..])
+        }
+        Shell::Fish => {
+            out.write_all(script.as_bytes())?;
+            out.write_all(FISH.as_bytes())
+        }
+        _ => out.write_all(script.as_bytes()),
+    }
+}
+
+/// Prints the models of the local catalog or the bundled regions for `complete-values`,
+/// one per line with a tab before the description.
+pub fn print_values(kind: &str, out: &mut dyn Write) -> io::Result<()> {
+    for (value, help) in values(kind) {
+        writeln!(out, "{value}\t{help}")?;
+    }
+    Ok(())
+}
+
+fn values(kind: &str) -> Vec<(String, String)> {
+    match kind {
+        "model" => {
+            let catalog = models::default_path()
+                .and_then(|path| Catalog::load(&path).ok())
+                .unwrap_or_default();
+            catalog
+                .models()
+                .iter()
+                .map(|m| (m.model.clone(), m.name.clone().unwrap_or_default()))
+                .collect()
+        }
+        "region" => regions::REGIONS
+            .iter()
+            .map(|r| {
+                let help = match r.carrier {
+                    Some(carrier) => format!("{} ({carrier})", r.country),
+                    None => r.country.to_owned(),
+                };
+                (r.code.to_owned(), help)
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+const BASH: &str = r#"
+_frigg_values() {
+    local kind
+    case "${COMP_WORDS[COMP_CWORD-1]}" in
+        -m|--model) kind=model ;;
+        -r|--region|--regions) kind=region ;;
+        *) _frigg "$@"; return ;;
+    esac
+    local values
+    values="$(frigg complete-values "$kind" 2>/dev/null | cut -f1)"
+    COMPREPLY=( $(compgen -W "${values}" -- "${COMP_WORDS[COMP_CWORD]}") )
+}
+
+complete -F _frigg_values -o bashdefault -o default frigg
+"#;
+
+const ZSH: &str = r#"(( $+functions[_frigg_values] )) ||
+_frigg_values() {
+    local -a values
+    values=(${${(f)"$(frigg complete-values $1 2>/dev/null)"}//$'\t'/:})
+    _describe -t $1 $1 values
+}
+
+"#;
+
+const FISH: &str = r#"complete -c frigg -s m -l model -x -a "(frigg complete-values model 2>/dev/null)"
+complete -c frigg -s r -l region -l regions -x -a "(frigg complete-values region 2>/dev/null)"
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::app;
+
+    fn script(shell: Shell) -> String {
+        let mut out = Vec::new();
+        generate(shell, &mut app(), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn dynamic_values() {
+        let regions = values("region");
+        let eux = regions.iter().find(|(code, _)| code == "EUX").unwrap();
+        assert_eq!(eux.1, "Europe");
+
+        let zsh = script(Shell::Zsh);
+        assert!(zsh.contains("'-m+[device model]:MODEL:_frigg_values model'"));
+        assert!(zsh.contains(":REGION:_frigg_values region'"));
+        assert!(zsh.find("_frigg_values() {") < zsh.find("if [ \"$funcstack[1]\""));
+        // Nothing is baked into the scripts.
+        assert!(!zsh.contains("Europe"));
+        assert!(!script(Shell::Bash).contains("EUX"));
+        assert!(script(Shell::Fish).contains("(frigg complete-values model 2>/dev/null)"));
+    }
+}
@@ -0,0 +1,165 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::Context;
+use clap::ArgMatches;
+use reqwest::{Certificate, Proxy};
+use serde::{Deserialize, Deserializer};
+
+use frigg::client::{Client, ClientBuilder, RetryPolicy, SMART_SWITCH_USER_AGENT};
+use frigg::Error;
+
+/// The settings of `config.toml` in the user's config directory.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    pub network: Network,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Network {
+    pub proxy: Option<String>,
+    pub cacert: Option<PathBuf>,
+    #[serde(deserialize_with = "duration")]
+    pub connect_timeout: Option<Duration>,
+    #[serde(deserialize_with = "duration")]
+    pub read_timeout: Option<Duration>,
+    pub user_agent: Option<String>,
+    pub retries: Option<u32>,
+    #[serde(deserialize_with = "duration")]
+    pub retry_delay: Option<Duration>,
+}
+
+impl Config {
+    /// Loads the config file from `path` or from the default location if it exists.
+    pub fn load(path: Option<&Path>) -> Result<Self, Error> {
+        let (path, required) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Self::default()),
+            },
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(content) => toml::from_str(&content)
+                .with_context(|| format!("invalid config file {}", path.display())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound && !required => Ok(Self::default()),
+            Err(e) => {
+                Err(Error::new(e).context(format!("failed to read config {}", path.display())))
+            }
+        }
+    }
+}
+
+pub fn default_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("frigg").join("config.toml"))
+}
+
+impl Network {
+    /// Overrides the settings with the options given on the command line.
+    pub fn update_from(&mut self, matches: &ArgMatches) {
+        if let Some(proxy) = matches.get_one::<String>("proxy") {
+            self.proxy = Some(proxy.clone());
+        }
+        if let Some(cacert) = matches.get_one::<PathBuf>("cacert") {
+            self.cacert = Some(cacert.clone());
+        }
+        if let Some(timeout) = matches.get_one::<Duration>("connect-timeout") {
+            self.connect_timeout = Some(*timeout);
+        }
+        if let Some(timeout) = matches.get_one::<Duration>("read-timeout") {
+            self.read_timeout = Some(*timeout);
+        }
+        if let Some(user_agent) = matches.get_one::<String>("user-agent") {
+            self.user_agent = Some(user_agent.clone());
+        }
+        if let Some(retries) = matches.get_one::<u32>("retries") {
+            self.retries = Some(*retries);
+        }
+        if let Some(delay) = matches.get_one::<Duration>("retry-delay") {
+            self.retry_delay = Some(*delay);
+        }
+    }
+
+    pub fn client_builder(&self) -> Result<ClientBuilder, Error> {
+        let mut retry = RetryPolicy::default();
+        if let Some(retries) = self.retries {
+            retry.max_attempts = retries.saturating_add(1);
+        }
+        if let Some(delay) = self.retry_delay {
+            retry.initial_delay = delay;
+        }
+        let mut builder = Client::builder().retry(retry);
+
+        if let Some(proxy) = &self.proxy {
+            let proxy = Proxy::all(proxy).with_context(|| format!("invalid proxy {proxy}"))?;
+            builder = builder.proxy(proxy);
+        }
+        if let Some(path) = &self.cacert {
+            let pem = fs::read(path)
+                .with_context(|| format!("failed to read certificates {}", path.display()))?;
+            let certs = Certificate::from_pem_bundle(&pem)
+                .with_context(|| format!("invalid certificates {}", path.display()))?;
+            for cert in certs {
+                builder = builder.add_root_certificate(cert);
+            }
+        }
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.read_timeout {
+            builder = builder.read_timeout(timeout);
+        }
+        if let Some(user_agent) = &self.user_agent {
+            builder = builder.user_agent(match user_agent.as_str() {
+                "smart-switch" => SMART_SWITCH_USER_AGENT,
+                user_agent => user_agent,
+            });
+        }
+        Ok(builder)
+    }
+}
+
+fn duration<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|s| humantime::parse_duration(&s).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_network() {
+        let config: Config = toml::from_str(
+            r#"
+            [network]
+            proxy = "socks5h://127.0.0.1:1080"
+            connect-timeout = "10s"
+            read-timeout = "1m 30s"
+            retries = 0
+            "#,
+        )
+        .unwrap();
+
+        let network = config.network;
+        assert_eq!(network.proxy.as_deref(), Some("socks5h://127.0.0.1:1080"));
+        assert_eq!(network.connect_timeout, Some(Duration::from_secs(10)));
+        assert_eq!(network.read_timeout, Some(Duration::from_secs(90)));
+        assert_eq!(network.retries, Some(0));
+        assert!(network.client_builder().is_ok());
+    }
+
+    #[test]
+    fn reject_unknown_keys() {
+        assert!(toml::from_str::<Config>("[network]\nproxy-url = \"\"").is_err());
+    }
+}
@@ -1,17 +1,29 @@
+use std::cmp;
 use std::fmt;
-use std::future::poll_fn;
+use std::fs::{File, Metadata, OpenOptions};
 use std::io;
+use std::path::Path;
 use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
 
-use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::sync::mpsc;
+use tokio::task::{self, JoinHandle};
 
-use aes::cipher::block_padding::{Pkcs7, UnpadError};
+use aes::cipher::block_padding::{Padding, Pkcs7, UnpadError};
 use aes::cipher::generic_array::{ArrayLength, GenericArray};
 use aes::cipher::{BlockDecrypt, InvalidLength, KeyInit};
 use aes::Aes128;
+use memmap2::{Mmap, MmapMut};
+use serde::Serialize;
 
-const BUF_SIZE: usize = 4128;
-const BLOCK_SIZE: usize = 4096;
+use crate::checksum::{Checksums, Digests, Hasher};
+use crate::progress::{Phase, Progress};
+
+pub const BLOCK_SIZE: usize = 16;
+const CHUNK_SIZE: usize = 4 * 1024 * 1024;
+pub const MAP_CHUNK_SIZE: usize = 64 * 1024 * 1024;
 
 #[derive(Debug)]
 pub enum Error {
@@ -20,7 +32,14 @@ pub enum Error {
     Unpad,
 }
 
-impl std::error::Error for Error {}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::InvalidKey | Self::Unpad => None,
+        }
+    }
+}
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -32,9 +51,33 @@ impl fmt::Display for Error {
     }
 }
 
+/// The size of the decrypted data and the checksums of both sides.
+#[derive(Debug, Serialize)]
+pub struct Output {
+    pub size: u64,
+    pub encrypted: Checksums,
+    pub decrypted: Checksums,
+}
+
 impl From<io::Error> for Error {
     fn from(e: io::Error) -> Self {
-        Self::Io(e)
+        // Unwrap decryption errors that were passed through an `io::Read` implementation.
+        match e.get_ref().map(|inner| inner.is::<Error>()) {
+            Some(true) => *e
+                .into_inner()
+                .and_then(|inner| inner.downcast().ok())
+                .expect("checked error type"),
+            _ => Self::Io(e),
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Io(e) => e,
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
     }
 }
 
@@ -50,55 +93,395 @@ impl From<UnpadError> for Error {
     }
 }
 
+/// Decrypts the input in large chunks on the blocking thread pool while the next chunks
+/// are read. Chunks are written in order and the last one is held back for unpadding.
 pub async fn decrypt<'a, R, W>(
     key: &[u8],
-    mut reader: &'a mut R,
+    reader: &'a mut R,
     writer: &'a mut W,
-) -> Result<u64, Error>
+) -> Result<Output, Error>
 where
     R: AsyncRead + Unpin + ?Sized,
     W: AsyncWrite + Unpin + ?Sized,
 {
-    let mut buf = vec![0; BUF_SIZE];
-    let mut buf = ReadBuf::new(&mut buf);
+    pipeline(key, reader, writer, CHUNK_SIZE, workers()).await
+}
+
+/// Returns whether [`decrypt_file`] can map a file. Pipes, special files and files
+/// larger than the address space have to go through [`decrypt`] instead.
+pub fn can_map(md: &Metadata) -> bool {
+    md.is_file() && usize::try_from(md.len()).is_ok()
+}
 
+/// Decrypts a local file through memory maps of the input and output file.
+///
+/// The output is synced before returning, so it can be renamed into place.
+pub fn decrypt_file<P>(
+    key: &[u8],
+    input: &Path,
+    output: &Path,
+    progress: P,
+) -> Result<Output, Error>
+where
+    P: Progress,
+{
     let cipher = Aes128::new_from_slice(key)?;
-    let mut eof = false;
-    let mut amt = 0;
-
-    while !eof {
-        while buf.remaining() > 0 {
-            let rem = buf.remaining();
-            poll_fn(|cx| Pin::new(&mut reader).poll_read(cx, &mut buf)).await?;
-            if buf.remaining() == rem {
-                eof = true;
-                break;
+
+    let input = File::open(input)?;
+    let len = usize::try_from(input.metadata()?.len()).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "file is too large to be mapped",
+        )
+    })?;
+    if len == 0 || len % BLOCK_SIZE != 0 {
+        return Err(Error::Unpad);
+    }
+
+    let out = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(output)?;
+    out.set_len(len as u64)?;
+
+    // SAFETY: the input is only read and the output was just created with the size of the
+    // input. Both are mapped until the end of this function and are the caller's local
+    // files, nothing else is expected to truncate them while they're decrypted.
+    #[allow(unsafe_code)]
+    let (src, mut dst) = unsafe { (Mmap::map(&input)?, MmapMut::map_mut(&out)?) };
+
+    progress.start(Phase::Decrypt, len as u64);
+    let workers = workers();
+    let (size, encrypted, decrypted) = std::thread::scope(|s| {
+        let encrypted = s.spawn(|| Checksums::compute(&src));
+
+        let mut decrypted = Digests::default();
+        let (mut offset, mut size) = (0, 0);
+        for (src, dst) in src
+            .chunks(MAP_CHUNK_SIZE)
+            .zip(dst.chunks_mut(MAP_CHUNK_SIZE))
+        {
+            dst.copy_from_slice(src);
+            decrypt_blocks_parallel(&cipher, dst, workers);
+            offset += dst.len();
+
+            // Only the last chunk ends with the padding.
+            let n = if offset == len {
+                unpadded_len(dst)?
+            } else {
+                dst.len() as u64
+            };
+            decrypted.update(&dst[..n as usize]);
+            size += n;
+            progress.inc(src.len() as u64);
+        }
+        let encrypted = encrypted.join().expect("checksum thread panicked");
+        Ok::<_, Error>((size, encrypted, decrypted.finish()))
+    })?;
+
+    dst.flush()?;
+    drop(dst);
+    out.set_len(size)?;
+    out.sync_all()?;
+    progress.finish(Phase::Decrypt);
+
+    Ok(Output {
+        size,
+        encrypted,
+        decrypted,
+    })
+}
+
+/// Checks if the last block of the ciphertext decrypts to a valid padding.
+pub fn check_key(key: &[u8], last_block: &[u8]) -> Result<(), Error> {
+    let cipher = Aes128::new_from_slice(key)?;
+    let mut block = aes::Block::clone_from_slice(last_block);
+    cipher.decrypt_block(&mut block);
+    Pkcs7::unpad(&block)?;
+    Ok(())
+}
+
+/// Returns the length of the decrypted data without the padding of the last block.
+pub fn unpadded_len(data: &[u8]) -> Result<u64, Error> {
+    let (data, last) = data.split_at(data.len() - BLOCK_SIZE);
+    let last = Pkcs7::unpad(aes::Block::from_slice(last))?;
+    Ok((data.len() + last.len()) as u64)
+}
+
+/// Splits the data into equal parts and decrypts them on `workers` threads.
+pub fn decrypt_blocks_parallel(cipher: &Aes128, data: &mut [u8], workers: usize) {
+    let part = ((data.len() / BLOCK_SIZE + workers - 1) / workers).max(1) * BLOCK_SIZE;
+    std::thread::scope(|s| {
+        for data in data.chunks_mut(part) {
+            s.spawn(|| cipher.decrypt_blocks(to_blocks(data)));
+        }
+    });
+}
+
+pub fn workers() -> usize {
+    std::thread::available_parallelism().map_or(1, usize::from)
+}
+
+type Job = JoinHandle<Result<Vec<u8>, Error>>;
+
+async fn pipeline<R, W>(
+    key: &[u8],
+    reader: &mut R,
+    writer: &mut W,
+    chunk_size: usize,
+    workers: usize,
+) -> Result<Output, Error>
+where
+    R: AsyncRead + Unpin + ?Sized,
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    debug_assert!(chunk_size % BLOCK_SIZE == 0);
+
+    let cipher = Aes128::new_from_slice(key)?;
+    let (tx, mut rx) = mpsc::channel::<Job>(workers);
+    let encrypted = Hasher::new();
+    let decrypted = Hasher::new();
+
+    let hasher = &encrypted;
+    let read = async move {
+        let mut chunk = vec![0; chunk_size];
+        let mut len = read_chunk(reader, &mut chunk).await?;
+
+        loop {
+            let mut next = vec![0; chunk_size];
+            let next_len = if len == chunk_size {
+                read_chunk(reader, &mut next).await?
+            } else {
+                0
+            };
+            let last = next_len == 0;
+
+            // The hasher and the decryption share the encrypted chunk.
+            chunk.truncate(len);
+            let encrypted = Arc::new(chunk);
+            hasher.update(Arc::clone(&encrypted)).await;
+
+            let cipher = cipher.clone();
+            let job = task::spawn_blocking(move || {
+                let mut chunk = vec![0; encrypted.len()];
+                if last {
+                    let n = cipher
+                        .decrypt_padded_b2b::<Pkcs7>(&encrypted, &mut chunk)?
+                        .len();
+                    chunk.truncate(n);
+                } else {
+                    let blocks = to_blocks(&mut chunk);
+                    cipher
+                        .decrypt_blocks_b2b(as_blocks(&encrypted), blocks)
+                        .expect("blocks of the same length");
+                }
+                Ok(chunk)
+            });
+            if tx.send(job).await.is_err() {
+                // The writer stopped early and will report its error.
+                return Ok(());
             }
+            if last {
+                return Ok::<_, Error>(());
+            }
+            chunk = next;
+            len = next_len;
         }
+    };
 
-        if buf.filled().len() > BLOCK_SIZE {
-            let new_filled = {
-                let (block, remainder) = buf.filled_mut().split_at_mut(BLOCK_SIZE);
-                cipher.decrypt_blocks(to_blocks(block));
-                writer.write_all(block).await?;
-                amt += block.len() as u64;
-                remainder.len()
+    let hasher = &decrypted;
+    let write = async move {
+        let mut amt = 0;
+        while let Some(job) = rx.recv().await {
+            let chunk = match job.await {
+                Ok(result) => result?,
+                Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+                // The runtime is shutting down.
+                Err(e) => return Err(io::Error::new(io::ErrorKind::Other, e).into()),
             };
-            buf.filled_mut().copy_within(BLOCK_SIZE.., 0);
-            buf.set_filled(new_filled);
-        } else {
-            let block = buf.filled_mut();
-            let buf = cipher.decrypt_padded::<Pkcs7>(block)?;
+            writer.write_all(&chunk).await?;
+            amt += chunk.len() as u64;
+            hasher.update(Arc::new(chunk)).await;
+        }
+        writer.flush().await?;
+        Ok::<_, Error>(amt)
+    };
+
+    let ((), size) = tokio::try_join!(read, write)?;
+    Ok(Output {
+        size,
+        encrypted: encrypted.finish().await,
+        decrypted: decrypted.finish().await,
+    })
+}
 
-            writer.write_all(buf).await?;
-            amt += buf.len() as u64;
+/// Fills `buf` from the reader and returns the number of bytes read, which is
+/// only less than `buf.len()` at the end of the input.
+async fn read_chunk<R>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize>
+where
+    R: AsyncRead + Unpin + ?Sized,
+{
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]).await? {
+            0 => break,
+            n => filled += n,
         }
     }
-    writer.flush().await?;
-    Ok(amt)
+    Ok(filled)
+}
+
+/// A reader that decrypts the firmware while it is read from the inner reader.
+///
+/// The last block is held back until the end of the input is reached so that
+/// the padding can be removed.
+pub struct DecryptReader<R> {
+    inner: R,
+    buf: DecryptBuf,
+}
+
+impl<R> DecryptReader<R> {
+    pub fn new(key: &[u8], inner: R) -> Result<Self, Error> {
+        Ok(Self {
+            inner,
+            buf: DecryptBuf::new(key)?,
+        })
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
 }
 
-fn to_blocks<N>(data: &mut [u8]) -> &mut [GenericArray<u8, N>]
+impl<R> AsyncRead for DecryptReader<R>
+where
+    R: AsyncRead + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        out: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        while !this.buf.has_output() {
+            this.buf.compact();
+            while this.buf.needs_input() {
+                let mut spare = ReadBuf::new(this.buf.spare());
+                ready!(Pin::new(&mut this.inner).poll_read(cx, &mut spare))?;
+                let n = spare.filled().len();
+                this.buf.advance(n);
+            }
+            this.buf.decrypt()?;
+        }
+
+        let n = this.buf.copy_to(out.initialize_unfilled());
+        out.advance(n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+const READ_BUF_SIZE: usize = 64 * 1024;
+
+/// The buffer of the decrypting readers with the decrypted data at the front
+/// followed by the ciphertext that is still to be decrypted.
+pub(crate) struct DecryptBuf {
+    cipher: Aes128,
+    buf: Box<[u8]>,
+    pos: usize,
+    decrypted: usize,
+    filled: usize,
+    eof: bool,
+    done: bool,
+    /// The padding was invalid, every further read fails instead of returning EOF.
+    failed: bool,
+}
+
+impl DecryptBuf {
+    pub(crate) fn new(key: &[u8]) -> Result<Self, Error> {
+        Ok(Self {
+            cipher: Aes128::new_from_slice(key)?,
+            buf: vec![0; 2 * READ_BUF_SIZE].into_boxed_slice(),
+            pos: 0,
+            decrypted: 0,
+            filled: 0,
+            eof: false,
+            done: false,
+            failed: false,
+        })
+    }
+
+    /// Returns `true` if there is decrypted data or the end of the input is reached.
+    pub(crate) fn has_output(&self) -> bool {
+        self.pos < self.decrypted || self.done
+    }
+
+    pub(crate) fn compact(&mut self) {
+        self.buf.copy_within(self.decrypted..self.filled, 0);
+        self.filled -= self.decrypted;
+        self.pos = 0;
+        self.decrypted = 0;
+    }
+
+    pub(crate) fn needs_input(&self) -> bool {
+        !self.eof && self.filled <= READ_BUF_SIZE
+    }
+
+    pub(crate) fn spare(&mut self) -> &mut [u8] {
+        &mut self.buf[self.filled..]
+    }
+
+    pub(crate) fn advance(&mut self, n: usize) {
+        self.filled += n;
+        self.eof = n == 0;
+    }
+
+    pub(crate) fn decrypt(&mut self) -> Result<(), Error> {
+        if self.failed {
+            return Err(Error::Unpad);
+        }
+        if self.eof {
+            let buf = &mut self.buf[..self.filled];
+            match self.cipher.decrypt_padded::<Pkcs7>(buf) {
+                Ok(data) => self.decrypted = data.len(),
+                Err(e) => {
+                    self.failed = true;
+                    return Err(e.into());
+                }
+            }
+            self.done = true;
+        } else {
+            // Keep at least one byte back so the last block is never decrypted here.
+            let n = (self.filled - 1) / BLOCK_SIZE * BLOCK_SIZE;
+            self.cipher.decrypt_blocks(to_blocks(&mut self.buf[..n]));
+            self.decrypted = n;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn copy_to(&mut self, out: &mut [u8]) -> usize {
+        let n = cmp::min(out.len(), self.decrypted - self.pos);
+        out[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+        self.pos += n;
+        n
+    }
+}
+
+fn as_blocks<N>(data: &[u8]) -> &[GenericArray<u8, N>]
+where
+    N: ArrayLength<u8>,
+{
+    let n = N::to_usize();
+    debug_assert!(data.len() % n == 0);
+
+    #[allow(unsafe_code)]
+    unsafe {
+        std::slice::from_raw_parts(data.as_ptr() as *const GenericArray<u8, N>, data.len() / n)
+    }
+}
+
+pub(crate) fn to_blocks<N>(data: &mut [u8]) -> &mut [GenericArray<u8, N>]
 where
     N: ArrayLength<u8>,
 {
@@ -110,3 +493,111 @@ where
         std::slice::from_raw_parts_mut(data.as_ptr() as *mut GenericArray<u8, N>, data.len() / n)
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    use aes::cipher::BlockEncrypt;
+
+    pub(crate) const KEY: [u8; 16] = *b"0123456789abcdef";
+
+    /// Returns `len` bytes of test data and the data encrypted with [`KEY`].
+    pub(crate) fn encrypted(len: usize) -> (Vec<u8>, Vec<u8>) {
+        let data = (0..len).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let cipher = Aes128::new_from_slice(&KEY).unwrap();
+        let encrypted = cipher.encrypt_padded_vec::<Pkcs7>(&data);
+        (data, encrypted)
+    }
+
+    async fn roundtrip(len: usize, chunk_size: usize, workers: usize) -> Vec<u8> {
+        let (data, encrypted) = encrypted(len);
+
+        let mut out = Vec::new();
+        let output = pipeline(&KEY, &mut &encrypted[..], &mut out, chunk_size, workers)
+            .await
+            .unwrap();
+
+        assert_eq!(output.size, len as u64);
+        assert_eq!(
+            output.encrypted.sha256,
+            Checksums::compute(&encrypted).sha256
+        );
+        assert_eq!(output.decrypted.sha256, Checksums::compute(&data).sha256);
+        assert_eq!(out, data);
+        out
+    }
+
+    #[tokio::test]
+    async fn decrypt_chunk_boundaries() {
+        for len in [1, 15, 16, 17, 63, 64, 65, 127, 128, 129, 1000] {
+            roundtrip(len, 64, 3).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn decrypt_single_worker() {
+        roundtrip(4096, 32, 1).await;
+    }
+
+    #[tokio::test]
+    async fn decrypt_empty_input() {
+        let mut out = Vec::new();
+        let result = pipeline(&KEY, &mut &[][..], &mut out, 64, 2).await;
+        assert!(matches!(result, Err(Error::Unpad)));
+    }
+
+    #[tokio::test]
+    async fn decrypt_reader_stream() {
+        use futures_util::stream;
+        use tokio_util::io::StreamReader;
+
+        let (data, encrypted) = encrypted(200_000);
+
+        let chunks = encrypted
+            .chunks(1000)
+            .map(|c| Ok::<_, io::Error>(io::Cursor::new(c.to_vec())));
+        let reader = StreamReader::new(stream::iter(chunks));
+        let mut reader = DecryptReader::new(&KEY, reader).unwrap();
+
+        let mut out = Vec::new();
+        tokio::io::copy(&mut reader, &mut out).await.unwrap();
+        assert_eq!(out, data);
+    }
+
+    #[test]
+    fn decrypt_file_mmap() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("firmware.zip.enc4");
+        let output = dir.path().join("firmware.zip");
+
+        let (data, encrypted) = encrypted(100_000);
+        std::fs::write(&input, encrypted).unwrap();
+
+        let result = decrypt_file(&KEY, &input, &output, |_: u64| {}).unwrap();
+        assert_eq!(result.size, data.len() as u64);
+        assert_eq!(result.decrypted.md5, Checksums::compute(&data).md5);
+        assert_eq!(std::fs::read(&output).unwrap(), data);
+
+        assert!(can_map(&std::fs::metadata(&input).unwrap()));
+        assert!(!can_map(&std::fs::metadata(dir.path()).unwrap()));
+    }
+
+    #[tokio::test]
+    async fn decrypt_truncated_input() {
+        let (_, encrypted) = encrypted(100);
+
+        let mut out = Vec::new();
+        let input = &encrypted[..encrypted.len() - 1];
+        let result = pipeline(&KEY, &mut &input[..], &mut out, 64, 2).await;
+        assert!(matches!(result, Err(Error::Unpad)));
+
+        // The reader keeps failing instead of ending the stream early.
+        let mut reader = DecryptReader::new(&KEY, input).unwrap();
+        let mut buf = [0; 256];
+        for _ in 0..2 {
+            let err = reader.read(&mut buf).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+}
@@ -0,0 +1,215 @@
+//! Comparison of two firmwares by their versions, sizes and partitions.
+
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::ap::Partition;
+
+/// The components of a `AP/CSC/CP` version string as returned by the FOTA server.
+pub const COMPONENTS: [&str; 3] = ["AP", "CSC", "CP"];
+
+/// The label of the optional fourth part of a version, which is usually a copy of AP.
+const FOURTH: &str = "4th part";
+
+/// The label of the bootloader revisions, see [`bootloader_revision`].
+pub const BOOTLOADER: &str = "Bootloader rev.";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ComponentChange {
+    pub component: &'static str,
+    pub from: String,
+    pub to: String,
+    pub changed: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Added,
+    Removed,
+    Changed,
+    Unchanged,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Added => "added",
+            Self::Removed => "removed",
+            Self::Changed => "changed",
+            Self::Unchanged => "unchanged",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PartitionChange {
+    pub name: String,
+    pub status: Status,
+    pub size_delta: i64,
+}
+
+/// Returns the AP, CSC and CP parts of a version, CP defaults to AP.
+pub fn components(version: &str) -> [&str; 3] {
+    let mut parts = version.split('/');
+    let ap = parts.next().unwrap_or_default();
+    let csc = parts.next().unwrap_or_default();
+    let cp = parts.next().filter(|s| !s.is_empty()).unwrap_or(ap);
+    [ap, csc, cp]
+}
+
+/// Returns the fourth part of a version, which defaults to AP.
+fn fourth(version: &str) -> &str {
+    version
+        .split('/')
+        .nth(3)
+        .filter(|s| !s.is_empty())
+        .unwrap_or(components(version)[0])
+}
+
+/// Returns the bootloader revision of the AP version like `G991BXXU5CVDD`, which is
+/// the fifth character from the end. Firmwares with a lower revision can't be flashed.
+pub fn bootloader_revision(version: &str) -> Option<char> {
+    let ap = components(version)[0];
+    ap.chars().rev().nth(4).filter(char::is_ascii_alphanumeric)
+}
+
+/// Compares the AP, CSC and CP parts of two versions. The fourth part is only
+/// compared if it isn't a copy of AP in either version. The bootloader revisions
+/// come last, `-` if a version doesn't have one.
+pub fn compare_versions(from: &str, to: &str) -> Vec<ComponentChange> {
+    let change = |component, from: &str, to: &str| ComponentChange {
+        component,
+        from: from.to_owned(),
+        to: to.to_owned(),
+        changed: from != to,
+    };
+    let mut changes = COMPONENTS
+        .iter()
+        .zip(components(from).into_iter().zip(components(to)))
+        .map(|(component, (from, to))| change(component, from, to))
+        .collect::<Vec<_>>();
+
+    let is_copy = |version| fourth(version) == components(version)[0];
+    if !is_copy(from) || !is_copy(to) {
+        changes.push(change(FOURTH, fourth(from), fourth(to)));
+    }
+
+    let revision =
+        |version| bootloader_revision(version).map_or_else(|| "-".to_owned(), String::from);
+    changes.push(change(BOOTLOADER, &revision(from), &revision(to)));
+    changes
+}
+
+/// Compares the files of two AP tarballs in the order of `to`, followed by the
+/// removed files.
+pub fn compare_partitions(from: &[Partition], to: &[Partition]) -> Vec<PartitionChange> {
+    let find = |list: &[Partition], name: &str| list.iter().find(|p| p.name == name).cloned();
+
+    let mut changes = to
+        .iter()
+        .map(|new| match find(from, &new.name) {
+            Some(old) => PartitionChange {
+                name: new.name.clone(),
+                status: if old.md5 == new.md5 && old.size == new.size {
+                    Status::Unchanged
+                } else {
+                    Status::Changed
+                },
+                size_delta: new.size as i64 - old.size as i64,
+            },
+            None => PartitionChange {
+                name: new.name.clone(),
+                status: Status::Added,
+                size_delta: new.size as i64,
+            },
+        })
+        .collect::<Vec<_>>();
+
+    changes.extend(
+        from.iter()
+            .filter(|old| find(to, &old.name).is_none())
+            .map(|old| PartitionChange {
+                name: old.name.clone(),
+                status: Status::Removed,
+                size_delta: -(old.size as i64),
+            }),
+    );
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions() {
+        let changes = compare_versions(
+            "G991BXXU5CVDD/G991BOXM5CVDD/G991BXXU5CVDD/G991BXXU5CVDD",
+            "G991BXXU5CVDD/G991BODM5CVDD/G991BXXU5CVDD",
+        );
+        let changed = changes
+            .iter()
+            .filter(|c| c.changed)
+            .map(|c| c.component)
+            .collect::<Vec<_>>();
+        assert_eq!(changed, ["CSC"]);
+        assert_eq!(changes.len(), 4);
+
+        let changes = compare_versions("A1/C/A1/X", "A1/C/A1");
+        assert_eq!(changes[3].component, FOURTH);
+        assert_eq!(
+            (changes[3].from.as_str(), changes[3].to.as_str()),
+            ("X", "A1")
+        );
+        assert_eq!(
+            bootloader_revision("G991BXXU5CVDD/G991BOXM5CVDD"),
+            Some('5')
+        );
+
+        let changes =
+            compare_versions("G991BXXU5CVDD/G991BOXM5CVDD", "G991BXXU6DWA1/G991BOXM6DWA1");
+        let bootloader = changes.last().unwrap();
+        assert_eq!(bootloader.component, BOOTLOADER);
+        assert_eq!(
+            (bootloader.from.as_str(), bootloader.to.as_str()),
+            ("5", "6")
+        );
+        assert!(bootloader.changed);
+        assert_eq!(compare_versions("A1/C", "A1/C").last().unwrap().from, "-");
+    }
+
+    #[test]
+    fn partitions() {
+        let partition = |name: &str, size, md5: &str| Partition {
+            name: name.to_owned(),
+            size,
+            md5: md5.to_owned(),
+        };
+        let from = [
+            partition("boot.img.lz4", 10, "a"),
+            partition("super.img.lz4", 100, "b"),
+            partition("userdata.img.lz4", 5, "c"),
+        ];
+        let to = [
+            partition("boot.img.lz4", 10, "a"),
+            partition("super.img.lz4", 120, "d"),
+            partition("vbmeta.img", 1, "e"),
+        ];
+        let changes = compare_partitions(&from, &to)
+            .into_iter()
+            .map(|c| (c.name, c.status, c.size_delta))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            [
+                ("boot.img.lz4".to_owned(), Status::Unchanged, 0),
+                ("super.img.lz4".to_owned(), Status::Changed, 20),
+                ("vbmeta.img".to_owned(), Status::Added, 1),
+                ("userdata.img.lz4".to_owned(), Status::Removed, -5),
+            ]
+        );
+        assert_eq!(Status::Unchanged.to_string(), "unchanged");
+    }
+}
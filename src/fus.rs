@@ -0,0 +1,295 @@
+//! Typed model of the XML messages that are exchanged with the FUS server.
+//!
+//! ```xml
+//! <FUSMsg>
+//!   <FUSHdr><ProtoVer>1.0</ProtoVer></FUSHdr>
+//!   <FUSBody>
+//!     <Results><Status>200</Status></Results>
+//!     <Put><BINARY_NAME><Data>...</Data></BINARY_NAME></Put>
+//!   </FUSBody>
+//! </FUSMsg>
+//! ```
+
+use std::fmt;
+
+use serde::de::{self, DeserializeOwned, MapAccess, Visitor};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::Error;
+
+const XML_DECL: &str = r#"<?xml version="1.0" encoding="utf-8"?>"#;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(
+    rename = "FUSMsg",
+    bound(deserialize = "P: Deserialize<'de> + Default")
+)]
+pub struct FusMsg<P = Fields> {
+    #[serde(rename = "FUSHdr", default)]
+    pub hdr: FusHdr,
+    #[serde(rename = "FUSBody")]
+    pub body: FusBody<P>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FusHdr {
+    #[serde(rename = "ProtoVer")]
+    pub proto_ver: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound(deserialize = "P: Deserialize<'de> + Default"))]
+pub struct FusBody<P> {
+    #[serde(rename = "Results", default, skip_serializing_if = "Option::is_none")]
+    pub results: Option<FusResults>,
+    #[serde(rename = "Put", default)]
+    pub put: P,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FusResults {
+    #[serde(rename = "Status")]
+    pub status: u32,
+    #[serde(
+        rename = "LATEST_FW_VERSION",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "data"
+    )]
+    pub latest_fw_version: Option<String>,
+}
+
+/// The `<NAME><Data>value</Data></NAME>` elements of a `Put` element in the
+/// order of the message.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Fields(Vec<(String, String)>);
+
+/// The `<Data>` element that wraps every value.
+#[derive(Serialize, Deserialize)]
+struct Data<T> {
+    #[serde(rename = "Data")]
+    data: T,
+}
+
+impl Default for FusHdr {
+    fn default() -> Self {
+        Self {
+            proto_ver: "1.0".to_owned(),
+        }
+    }
+}
+
+impl<P> FusMsg<P> {
+    pub fn new(put: P) -> Self {
+        Self {
+            hdr: FusHdr::default(),
+            body: FusBody { results: None, put },
+        }
+    }
+
+    pub fn status(&self) -> Option<u32> {
+        self.body.results.as_ref().map(|r| r.status)
+    }
+}
+
+impl<P: Serialize> FusMsg<P> {
+    pub fn to_xml(&self) -> Result<String, Error> {
+        let mut xml = XML_DECL.to_owned();
+        self.serialize(quick_xml::se::Serializer::new(&mut xml))?;
+        Ok(xml)
+    }
+}
+
+impl<P: DeserializeOwned + Default> FusMsg<P> {
+    pub fn from_xml(xml: &str) -> Result<Self, Error> {
+        quick_xml::de::from_str(xml).map_err(Into::into)
+    }
+}
+
+impl Fields {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Adds a field or replaces the value of an existing field.
+    pub fn insert(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        let (name, value) = (name.into(), value.into());
+        match self.0.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, v)) => *v = value,
+            None => self.0.push((name, value)),
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<String> {
+        let pos = self.0.iter().position(|(n, _)| n == name)?;
+        Some(self.0.remove(pos).1)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl<N: Into<String>, V: Into<String>> FromIterator<(N, V)> for Fields {
+    fn from_iter<T: IntoIterator<Item = (N, V)>>(iter: T) -> Self {
+        let mut fields = Fields::new();
+        for (name, value) in iter {
+            fields.insert(name, value);
+        }
+        fields
+    }
+}
+
+impl IntoIterator for Fields {
+    type Item = (String, String);
+    type IntoIter = std::vec::IntoIter<(String, String)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl Serialize for Fields {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (name, value) in &self.0 {
+            map.serialize_entry(name, &Data { data: value })?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Fields {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FieldsVisitor;
+
+        impl<'de> Visitor<'de> for FieldsVisitor {
+            type Value = Fields;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a list of fields")
+            }
+
+            fn visit_str<E: de::Error>(self, _: &str) -> Result<Self::Value, E> {
+                // `<Put />` is an empty string for the deserializer.
+                Ok(Fields::new())
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut fields = Fields::new();
+                while let Some((name, value)) = map.next_entry::<String, Data<Option<String>>>()? {
+                    fields.insert(name, value.data.unwrap_or_default());
+                }
+                Ok(fields)
+            }
+        }
+
+        deserializer.deserialize_map(FieldsVisitor)
+    }
+}
+
+mod data {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::Data;
+
+    pub fn serialize<S: Serializer>(value: &Option<String>, s: S) -> Result<S::Ok, S::Error> {
+        value.as_ref().map(|data| Data { data }).serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<String>, D::Error> {
+        let value = Option::<Data<Option<String>>>::deserialize(d)?;
+        Ok(value.map(|v| v.data.unwrap_or_default()))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A `NF_DownloadBinaryInform.do` response, shared with the tests of the modules
+    /// that parse it.
+    pub(crate) const BINARY_INFORM_XML: &str = "<FUSMsg><FUSHdr><ProtoVer>1.0</ProtoVer></FUSHdr><FUSBody>\
+        <Results><Status>200</Status>\
+        <LATEST_FW_VERSION><Data>G991BXXU5CVDD/G991BOXM5CVDD/G991BXXU5CVDD/G991BXXU5CVDD</Data></LATEST_FW_VERSION>\
+        </Results><Put>\
+        <BINARY_NAME><Data>SM-G991B_1_20220412_abcdefghij_fac.zip.enc4</Data></BINARY_NAME>\
+        <BINARY_BYTE_SIZE><Data>1234</Data></BINARY_BYTE_SIZE>\
+        <BINARY_CRC><Data>3735928559</Data></BINARY_CRC>\
+        <BINARY_RELEASE_DATE><Data>20220412</Data></BINARY_RELEASE_DATE>\
+        <LOGIC_VALUE_FACTORY><Data>abcdefghijklmnop</Data></LOGIC_VALUE_FACTORY>\
+        <LOGIC_VALUE_HOME><Data/></LOGIC_VALUE_HOME>\
+        <DEVICE_MODEL_DISPLAYNAME><Data>Galaxy S21 5G</Data></DEVICE_MODEL_DISPLAYNAME>\
+        <DEVICE_PLATFORM><Data>Android</Data></DEVICE_PLATFORM>\
+        <CURRENT_OS_VERSION><Data>S(Android 12)</Data></CURRENT_OS_VERSION>\
+        <MODEL_PATH><Data>/neofus/9/</Data></MODEL_PATH>\
+        <DESCRIPTION><Data>http://doc.samsungmobile.com/SM-G991B/EUX/doc.html</Data></DESCRIPTION>\
+        <SOME_NEW_FIELD><Data>x &amp; y</Data></SOME_NEW_FIELD>\
+        </Put></FUSBody></FUSMsg>";
+
+    #[test]
+    fn parse_response() {
+        let msg = FusMsg::<Fields>::from_xml(BINARY_INFORM_XML).unwrap();
+        assert_eq!(msg.status(), Some(200));
+        let results = msg.body.results.as_ref().unwrap();
+        assert_eq!(
+            results.latest_fw_version.as_deref(),
+            Some("G991BXXU5CVDD/G991BOXM5CVDD/G991BXXU5CVDD/G991BXXU5CVDD")
+        );
+
+        let put = &msg.body.put;
+        assert_eq!(put.len(), 12);
+        assert_eq!(put.get("BINARY_BYTE_SIZE"), Some("1234"));
+        assert_eq!(put.get("LOGIC_VALUE_HOME"), Some(""));
+        assert_eq!(put.get("SOME_NEW_FIELD"), Some("x & y"));
+        assert_eq!(put.get("MISSING"), None);
+    }
+
+    #[test]
+    fn round_trip() {
+        let msg = FusMsg::<Fields>::from_xml(BINARY_INFORM_XML).unwrap();
+        let xml = msg.to_xml().unwrap();
+        assert!(xml.starts_with(XML_DECL));
+        assert_eq!(FusMsg::from_xml(&xml).unwrap(), msg);
+
+        let msg = FusMsg::new(
+            [("ACCESS_MODE", "2"), ("LOGIC_CHECK", "abc")]
+                .into_iter()
+                .collect(),
+        );
+        let xml = msg.to_xml().unwrap();
+        assert_eq!(
+            xml,
+            r#"<?xml version="1.0" encoding="utf-8"?><FUSMsg><FUSHdr><ProtoVer>1.0</ProtoVer></FUSHdr><FUSBody><Put><ACCESS_MODE><Data>2</Data></ACCESS_MODE><LOGIC_CHECK><Data>abc</Data></LOGIC_CHECK></Put></FUSBody></FUSMsg>"#
+        );
+        assert_eq!(FusMsg::<Fields>::from_xml(&xml).unwrap(), msg);
+    }
+
+    #[test]
+    fn empty_put() {
+        let msg = FusMsg::new(Fields::new());
+        let xml = msg.to_xml().unwrap();
+        assert_eq!(FusMsg::<Fields>::from_xml(&xml).unwrap(), msg);
+
+        let xml = "<FUSMsg><FUSBody><Results><Status>401</Status></Results></FUSBody></FUSMsg>";
+        let msg = FusMsg::<Fields>::from_xml(xml).unwrap();
+        assert_eq!(msg.status(), Some(401));
+        assert!(msg.body.put.is_empty());
+    }
+}
@@ -0,0 +1,312 @@
+use std::cmp;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use aes::cipher::KeyInit;
+use aes::Aes128;
+use memmap2::MmapMut;
+use serde::Serialize;
+
+use crate::checksum::{Checksums, Digests};
+
+use crate::decrypt::{self, Error, BLOCK_SIZE, MAP_CHUNK_SIZE};
+use crate::progress::{Phase, Progress};
+
+const MAGIC: &[u8; 8] = b"FRIGGJNL";
+const HEADER_LEN: u64 = 32;
+
+/// The size of the decrypted file and its checksums.
+#[derive(Debug, Serialize)]
+pub struct Output {
+    pub size: u64,
+    /// `None` if an interrupted run was continued, the encrypted data is gone then.
+    pub encrypted: Option<Checksums>,
+    pub decrypted: Checksums,
+}
+
+/// Decrypts the file at `path` by overwriting it chunk by chunk and renames it to `dest`.
+///
+/// Each chunk is backed up to a journal next to the file before it is overwritten, so an
+/// interrupted run restores the chunk and continues from the last finished offset.
+/// The checksums are computed while decrypting, a continued run reads the decrypted
+/// file again for them.
+pub fn decrypt_in_place<P>(
+    key: &[u8],
+    path: &Path,
+    dest: &Path,
+    progress: P,
+) -> Result<Output, Error>
+where
+    P: Progress,
+{
+    let cipher = Aes128::new_from_slice(key)?;
+    if is_finished(path, dest) {
+        fs::remove_file(journal_path(path))?;
+        return Ok(Output {
+            size: fs::metadata(dest)?.len(),
+            encrypted: None,
+            decrypted: Checksums::from_reader(File::open(dest)?)?,
+        });
+    }
+    let file = OpenOptions::new().read(true).write(true).open(path)?;
+    let len = file.metadata()?.len();
+
+    let journal_path = journal_path(path);
+    let resumed = journal_path.exists();
+    let mut digests = (!resumed).then(|| (Digests::default(), Digests::default()));
+    let mut journal = if resumed {
+        Journal::open(&journal_path)?
+    } else {
+        if len == 0 || len % BLOCK_SIZE as u64 != 0 {
+            return Err(Error::Unpad);
+        }
+        let mut last_block = [0; BLOCK_SIZE];
+        (&file).seek(SeekFrom::Start(len - BLOCK_SIZE as u64))?;
+        (&file).read_exact(&mut last_block)?;
+        decrypt::check_key(key, &last_block)?;
+
+        Journal::create(&journal_path, len)?
+    };
+
+    if journal.done < journal.total {
+        if len != journal.total {
+            return Err(invalid_journal("file size doesn't match the journal").into());
+        }
+        let total = usize::try_from(journal.total).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "file is too large to be mapped",
+            )
+        })?;
+
+        // SAFETY: the map covers the size that was checked against the journal above and
+        // the file is only written through it until it's renamed. Nothing else is expected
+        // to open the encrypted file while it's being decrypted in place.
+        #[allow(unsafe_code)]
+        let mut map = unsafe { MmapMut::map_mut(&file)? };
+
+        if let Some(backup) = journal.pending()? {
+            let start = journal.done as usize;
+            map[start..start + backup.len()].copy_from_slice(&backup);
+            map.flush_range(start, backup.len())?;
+        }
+        progress.start(Phase::Decrypt, journal.total);
+        progress.inc(journal.done);
+
+        let workers = decrypt::workers();
+        while journal.done < journal.total {
+            let start = journal.done as usize;
+            let end = cmp::min(start + MAP_CHUNK_SIZE, total);
+            let chunk = &mut map[start..end];
+
+            journal.begin(chunk)?;
+            if let Some((encrypted, _)) = &mut digests {
+                encrypted.update(chunk);
+            }
+            decrypt::decrypt_blocks_parallel(&cipher, chunk, workers);
+            if let Some((_, decrypted)) = &mut digests {
+                // Only the last chunk ends with the padding.
+                let n = if end == total {
+                    decrypt::unpadded_len(chunk)? as usize
+                } else {
+                    chunk.len()
+                };
+                decrypted.update(&chunk[..n]);
+            }
+            map.flush_range(start, end - start)?;
+            journal.commit(end as u64)?;
+
+            progress.inc((end - start) as u64);
+        }
+    }
+
+    // The padding is still present unless a previous run was interrupted after truncating.
+    let amt = if len == journal.total {
+        let mut last_block = [0; BLOCK_SIZE];
+        (&file).seek(SeekFrom::Start(len - BLOCK_SIZE as u64))?;
+        (&file).read_exact(&mut last_block)?;
+        let amt = len - BLOCK_SIZE as u64 + decrypt::unpadded_len(&last_block)?;
+
+        file.set_len(amt)?;
+        file.sync_all()?;
+        amt
+    } else {
+        len
+    };
+    drop(file);
+
+    fs::rename(path, dest)?;
+    fs::remove_file(journal_path)?;
+    progress.finish(Phase::Decrypt);
+
+    let (encrypted, decrypted) = match digests {
+        Some((encrypted, decrypted)) => (Some(encrypted.finish()), decrypted.finish()),
+        None => (None, Checksums::from_reader(File::open(dest)?)?),
+    };
+    Ok(Output {
+        size: amt,
+        encrypted,
+        decrypted,
+    })
+}
+
+/// Returns whether a run was interrupted after `path` was renamed to `dest`, the
+/// decrypted file at `dest` is complete then and only the journal is left over.
+pub fn is_finished(path: &Path, dest: &Path) -> bool {
+    !path.exists() && dest.exists() && journal_path(path).exists()
+}
+
+fn journal_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".journal");
+    path.with_file_name(name)
+}
+
+fn invalid_journal(msg: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid journal: {msg}"),
+    )
+}
+
+/// The journal starts with a header of the encrypted size, the decrypted offset and the
+/// length of the backup of the chunk that is being decrypted, followed by the backup.
+struct Journal {
+    file: File,
+    total: u64,
+    done: u64,
+}
+
+impl Journal {
+    fn create(path: &Path, total: u64) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(path)?;
+
+        let mut journal = Self {
+            file,
+            total,
+            done: 0,
+        };
+        journal.write_header(0)?;
+        Ok(journal)
+    }
+
+    fn open(path: &Path) -> io::Result<Self> {
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+
+        let mut header = [0; HEADER_LEN as usize];
+        file.read_exact(&mut header)
+            .map_err(|_| invalid_journal("missing header"))?;
+        if &header[..8] != MAGIC {
+            return Err(invalid_journal("unknown format"));
+        }
+
+        let total = read_u64(&header[8..16]);
+        let done = read_u64(&header[16..24]);
+        if done > total {
+            return Err(invalid_journal("offset out of range"));
+        }
+        Ok(Self { file, total, done })
+    }
+
+    /// Returns the backup of the chunk that was being decrypted when the run was interrupted.
+    fn pending(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut header = [0; HEADER_LEN as usize];
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.read_exact(&mut header)?;
+
+        match read_u64(&header[24..32]) {
+            0 => Ok(None),
+            len if len > self.total - self.done => Err(invalid_journal("backup out of range")),
+            len => {
+                let mut backup = vec![0; len as usize];
+                self.file.read_exact(&mut backup)?;
+                Ok(Some(backup))
+            }
+        }
+    }
+
+    fn begin(&mut self, chunk: &[u8]) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(HEADER_LEN))?;
+        self.file.write_all(chunk)?;
+        self.file.sync_data()?;
+        self.write_header(chunk.len() as u64)
+    }
+
+    fn commit(&mut self, done: u64) -> io::Result<()> {
+        self.done = done;
+        self.write_header(0)
+    }
+
+    fn write_header(&mut self, pending: u64) -> io::Result<()> {
+        let mut header = [0; HEADER_LEN as usize];
+        header[..8].copy_from_slice(MAGIC);
+        header[8..16].copy_from_slice(&self.total.to_le_bytes());
+        header[16..24].copy_from_slice(&self.done.to_le_bytes());
+        header[24..32].copy_from_slice(&pending.to_le_bytes());
+
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&header)?;
+        self.file.sync_data()
+    }
+}
+
+fn read_u64(buf: &[u8]) -> u64 {
+    u64::from_le_bytes(buf.try_into().expect("8 bytes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::decrypt::tests::{encrypted, KEY};
+
+    #[test]
+    fn resume_interrupted_chunk() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("firmware.zip.enc4");
+        let dest = dir.path().join("firmware.zip");
+
+        let (data, encrypted) = encrypted(10_000);
+        let cipher = Aes128::new_from_slice(&KEY).unwrap();
+
+        // Simulate a run that was interrupted while the first half was being overwritten.
+        let mut partial = encrypted.clone();
+        decrypt::decrypt_blocks_parallel(&cipher, &mut partial[..4096], 2);
+        fs::write(&path, &partial).unwrap();
+
+        let mut journal = Journal::create(&journal_path(&path), encrypted.len() as u64).unwrap();
+        journal.begin(&encrypted).unwrap();
+        drop(journal);
+
+        let output = decrypt_in_place(&KEY, &path, &dest, |_: u64| {}).unwrap();
+        assert_eq!(output.size, data.len() as u64);
+        assert!(output.encrypted.is_none());
+        assert_eq!(output.decrypted.sha256, Checksums::compute(&data).sha256);
+        assert_eq!(fs::read(&dest).unwrap(), data);
+        assert!(!path.exists());
+        assert!(!journal_path(&path).exists());
+
+        // Interrupted between the rename and the removal of the journal.
+        Journal::create(&journal_path(&path), encrypted.len() as u64).unwrap();
+        assert!(is_finished(&path, &dest));
+        let output = decrypt_in_place(&KEY, &path, &dest, |_: u64| {}).unwrap();
+        assert_eq!(output.size, data.len() as u64);
+        assert!(!journal_path(&path).exists());
+        fs::remove_file(&dest).unwrap();
+
+        // A fresh run hashes both sides while decrypting.
+        fs::write(&path, &encrypted).unwrap();
+        let output = decrypt_in_place(&KEY, &path, &dest, |_: u64| {}).unwrap();
+        assert_eq!(
+            output.encrypted.unwrap().sha256,
+            Checksums::compute(&encrypted).sha256
+        );
+        assert_eq!(output.decrypted.sha256, Checksums::compute(&data).sha256);
+        assert_eq!(fs::read(&dest).unwrap(), data);
+    }
+}
@@ -0,0 +1,28 @@
+//! Download and decrypt stock firmwares for Samsung devices.
+
+pub mod ap;
+pub mod auth;
+pub mod binary_info;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+pub mod changelog;
+pub mod checksum;
+pub mod client;
+pub mod decrypt;
+pub mod diff;
+pub mod fus;
+pub mod in_place;
+pub mod models;
+pub mod os_info;
+pub mod profile;
+pub mod progress;
+pub mod record;
+pub mod regions;
+mod requests;
+pub mod scan;
+pub mod schedule;
+pub mod throttle;
+pub mod version;
+mod xml;
+
+pub type Error = anyhow::Error;
@@ -0,0 +1,51 @@
+//! Setup of the log output from `-q/-v`, `--log-format` and `--log-file`.
+//!
+//! `RUST_LOG` takes precedence over `-q/-v` if it's set.
+
+use std::fs::OpenOptions;
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use anyhow::Context;
+use clap::ArgMatches;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::EnvFilter;
+
+use frigg::Error;
+
+pub fn init(matches: &ArgMatches) -> Result<(), Error> {
+    let directives = if matches.get_flag("quiet") {
+        "error"
+    } else {
+        match matches.get_count("v") + matches.get_count("verbose") {
+            0 => "warn",
+            1 => "warn,frigg=info",
+            2 => "warn,frigg=debug",
+            _ => "trace",
+        }
+    };
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(directives));
+
+    let (writer, ansi) = match matches.get_one::<PathBuf>("log-file") {
+        Some(path) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("failed to open log file {}", path.display()))?;
+            (BoxMakeWriter::new(Mutex::new(file)), false)
+        }
+        None => (BoxMakeWriter::new(io::stderr), io::stderr().is_terminal()),
+    };
+
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(writer)
+        .with_ansi(ansi);
+    match matches.get_one::<String>("log-format").map(String::as_str) {
+        Some("json") => builder.json().init(),
+        _ => builder.init(),
+    }
+    Ok(())
+}
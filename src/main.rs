@@ -1,161 +1,435 @@
 use std::borrow::Cow;
-use std::io;
+use std::io::{self, IsTerminal, SeekFrom, Write};
+use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
 
+use clap::builder::NonEmptyStringValueParser;
 use clap::{crate_description, crate_name, crate_version};
-use clap::{ArgAction, Command};
-use futures_util::TryStreamExt;
-use tokio::fs::File;
-use tokio::io::{BufReader, BufWriter};
+use clap::{Arg, ArgAction, ArgMatches, Command, ValueHint};
+use clap_complete::Shell;
+use indicatif::{HumanBytes, ProgressDrawTarget};
+use serde::Serialize;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, BufReader, BufWriter};
+use tokio_util::either::Either;
 use tokio_util::io::StreamReader;
 
-mod auth;
-mod binary_info;
-mod client;
-mod commands;
-mod decrypt;
-mod progress;
-mod requests;
-mod version;
-mod xml;
+use frigg::binary_info::{BinaryInfo, DecryptKey};
+use frigg::changelog::Entry;
+use frigg::checksum::{self, Checksums, Hasher};
+use frigg::client::Client;
+use frigg::diff::{self, ComponentChange, PartitionChange};
+use frigg::models::{self, Catalog, Model};
+use frigg::os_info::OsInfo;
+use frigg::profile::{BinaryNature, Profile};
+use frigg::progress::{self, Phase, Progress};
+use frigg::regions::{self, Region};
+use frigg::scan::{self, Found};
+use frigg::schedule::{self, Window};
+use frigg::{ap, decrypt, in_place, record, throttle, Error};
 
-use binary_info::{BinaryInfo, DecryptKey};
-use client::Client;
-use commands::{opt, path_arg, required_opt, required_path_arg, ArgMatchesExt, CommandExt};
+mod commands;
+mod completions;
+mod config;
+mod logging;
+mod output;
+mod reporter;
+mod serve;
 
-type Error = anyhow::Error;
+use commands::{check_region, opt, parse_rate, parse_target, path_arg, required_opt};
+use commands::{model_arg, required_path_arg, Target};
+use commands::{ArgMatchesExt, CommandExt};
+use config::Config;
+use output::{Partial, Policy};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tracing_subscriber::fmt::init();
-
-    let app = Command::new(crate_name!())
-        .about(crate_description!())
-        .version(crate_version!())
-        .arg_required_else_help(true)
-        .subcommand(
-            Command::new("check")
-                .about("check for the lastest available firmware version")
-                .args_model_imei_region(),
-        )
-        .subcommand(
-            Command::new("download")
-                .about("download the latest firmware")
-                .args_model_imei_region()
-                .arg(
-                    opt("download-only", "don't decrypt the firmware file")
-                        .action(ArgAction::SetTrue),
-                )
-                .arg(
-                    path_arg("output", "output to a specific file or directory")
-                        .value_name("OUTPUT"),
-                ),
-        )
-        .subcommand(
-            Command::new("decrypt")
-                .about("decrypt a downloaded firmware")
-                .args_model_imei_region()
-                .arg(
-                    required_opt("firmware-version", "")
-                        .short('v')
-                        .value_name("VERSION"),
-                )
-                .arg(required_path_arg("input", "path to encrypted firmware").value_name("INPUT"))
-                .arg(
-                    path_arg("output", "output to a specific file or directory")
-                        .value_name("OUTPUT"),
-                ),
-        );
+    let mut app = app();
+    let matches = app.get_matches_mut();
+    logging::init(&matches)?;
+    let events = match matches.get_one::<String>("progress").map(String::as_str) {
+        Some("json") => {
+            let out: Box<dyn Write + Send> = match matches.get_one::<i32>("progress-fd") {
+                Some(fd) => Box::new(reporter::open_fd(*fd)?),
+                None => Box::new(io::stderr()),
+            };
+            reporter::set_enabled(false);
+            Some(reporter::events(out))
+        }
+        Some("none") => {
+            reporter::set_enabled(false);
+            None
+        }
+        _ => {
+            reporter::set_enabled(!matches.get_flag("quiet") && io::stdout().is_terminal());
+            None
+        }
+    };
+    if let Some((_, matches)) = matches.subcommand() {
+        check_region(matches);
+    }
 
-    match app.get_matches().subcommand() {
+    match matches.subcommand() {
         Some(("check", matches)) => {
             let model = matches.get_model().expect("arg is required");
             let imei = matches.get_imei().expect("arg is required");
             let region = matches.get_region().expect("arg is required");
 
-            let client = Client::new()?;
+            let client = new_client(matches)?;
             let version = client.fetch_version(model, region).await?;
             let mut nonce = client.generate_nonce().await?;
             let info = client
                 .file_info(model, imei, region, &version, &mut nonce)
                 .await?;
+            remember(model, &info.display_name, region);
+
+            let mut os = OsInfo::from_binary_info(&info);
+            if os.security_patch.is_none() {
+                if let Some(url) = &info.description_url {
+                    match client.fetch_changelog(url).await {
+                        Ok(html) => os = os.or(OsInfo::from_changelog(&html)),
+                        Err(e) => tracing::warn!("failed to fetch the changelog: {e}"),
+                    }
+                }
+            }
 
-            print_info(model, region, &info);
+            if matches.get_flag("json") {
+                let check = Check {
+                    model,
+                    region,
+                    info: &info,
+                    os: &os,
+                };
+                println!("{}", serde_json::to_string_pretty(&check)?);
+            } else {
+                print_info(model, region, &info, &os, matches.get_flag("all"));
+            }
         }
-        Some(("download", matches)) => {
+        Some(("changelog", matches)) => {
             let model = matches.get_model().expect("arg is required");
-            let imei = matches.get_imei().expect("arg is required");
             let region = matches.get_region().expect("arg is required");
 
-            let output = match matches.get_one::<PathBuf>("output") {
-                Some(output) if output.is_dir() => Some(Destination::Dir(output)),
-                Some(output) if !output.exists() => Some(Destination::File(output)),
-                Some(_) | None => None,
+            let client = new_client(matches)?;
+            let mut entries = client.changelog(model, region).await?;
+            if let Some(version) = matches.get_one::<String>("firmware-version") {
+                entries.retain(|e| e.matches_version(version));
+                if entries.is_empty() {
+                    return Err(format!("no changelog for version {version}").into());
+                }
+            }
+
+            if matches.get_flag("json") {
+                println!("{}", serde_json::to_string_pretty(&entries)?);
+            } else {
+                print_changelog(&entries);
+            }
+        }
+        Some(("diff", matches)) => {
+            let imei = matches.get_imei().expect("arg is required");
+            let from = matches.get_one::<Target>("from").expect("arg is required");
+            let to = matches.get_one::<Target>("to").expect("arg is required");
+
+            let client = new_client(matches)?;
+            let mut nonce = client.generate_nonce().await?;
+            let mut infos = Vec::with_capacity(2);
+            for target in [from, to] {
+                let version = match &target.version {
+                    Some(version) => version.clone(),
+                    None => client.fetch_version(&target.model, &target.region).await?,
+                };
+                let info = client
+                    .file_info(&target.model, imei, &target.region, &version, &mut nonce)
+                    .await?;
+                infos.push(info);
+            }
+            for (target, info) in [from, to].into_iter().zip(&infos) {
+                remember(&target.model, &info.display_name, &target.region);
+            }
+
+            let partitions = match (
+                matches.get_one::<PathBuf>("from-firmware"),
+                matches.get_one::<PathBuf>("to-firmware"),
+            ) {
+                (Some(from_firmware), Some(to_firmware)) => {
+                    let multi = reporter::Multi::new(events.clone());
+                    let read = |target: &Target, path: &PathBuf| {
+                        let name = format!("{}/{}", target.model, target.region);
+                        let (path, pb) = (path.clone(), multi.add(&name, Phase::Extract, 0));
+                        tokio::task::spawn_blocking(move || {
+                            let partitions = ap::partitions(&path, pb.clone());
+                            pb.finish_and_clear();
+                            partitions
+                        })
+                    };
+                    let (from_partitions, to_partitions) =
+                        tokio::try_join!(read(from, from_firmware), read(to, to_firmware))?;
+                    multi.clear();
+                    Some(diff::compare_partitions(&from_partitions?, &to_partitions?))
+                }
+                _ => None,
+            };
+
+            let side = |target: &Target, info: &BinaryInfo| DiffSide {
+                model: target.model.clone(),
+                region: target.region.clone(),
+                version: info.version.clone(),
+                size: info.binary_size,
+            };
+            let diff = Diff {
+                from: side(from, &infos[0]),
+                to: side(to, &infos[1]),
+                components: diff::compare_versions(&infos[0].version, &infos[1].version),
+                size_delta: infos[1].binary_size as i64 - infos[0].binary_size as i64,
+                partitions,
+            };
+
+            if matches.get_flag("json") {
+                println!("{}", serde_json::to_string_pretty(&diff)?);
+            } else {
+                print_diff(&diff);
+            }
+        }
+        Some(("scan", matches)) => {
+            let model = matches.get_model().expect("arg is required");
+            let imei = matches.get_one::<String>("imei").map(String::as_str);
+            let jobs = *matches.get_one::<usize>("jobs").expect("has default");
+            let json = matches.get_flag("json");
+
+            let codes = match (
+                matches.get_many::<String>("regions"),
+                matches.get_one::<String>("country"),
+            ) {
+                (Some(codes), _) => codes.map(String::as_str).collect(),
+                (None, Some(country)) => regions::by_country(country)
+                    .iter()
+                    .map(|r| r.code)
+                    .collect(),
+                (None, None) => regions::REGIONS.iter().map(|r| r.code).collect::<Vec<_>>(),
+            };
+
+            let client = new_client(matches)?;
+            let pb = reporter::counter(codes.len() as u64);
+            if json {
+                pb.set_draw_target(ProgressDrawTarget::hidden());
+            }
+            let results = scan::scan(&client, model, &codes, jobs, imei, |found| {
+                pb.set_message(found.region.clone());
+                pb.inc(1);
+            })
+            .await?;
+            pb.finish_and_clear();
+
+            if json {
+                println!("{}", serde_json::to_string_pretty(&results)?);
+            } else {
+                print_scan(&results);
+            }
+        }
+        Some(("models", matches)) => {
+            let path = models::default_path().ok_or("no data directory for the model catalog")?;
+            let mut catalog = Catalog::load(&path)?;
+
+            match matches.subcommand() {
+                Some(("search", matches)) => {
+                    let query = matches
+                        .get_many::<String>("query")
+                        .expect("arg is required")
+                        .map(String::as_str)
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    let found = catalog.search(&query);
+                    if matches.get_flag("json") {
+                        println!("{}", serde_json::to_string_pretty(&found)?);
+                    } else if found.is_empty() {
+                        println!("No models found for \"{query}\" in {}", path.display());
+                    } else {
+                        print_models(&found);
+                    }
+                }
+                Some(("list", matches)) => {
+                    let list = catalog.models().iter().collect::<Vec<_>>();
+                    if matches.get_flag("json") {
+                        println!("{}", serde_json::to_string_pretty(&list)?);
+                    } else if list.is_empty() {
+                        println!("No models in {}", path.display());
+                    } else {
+                        print_models(&list);
+                    }
+                }
+                Some(("add", matches)) => {
+                    let model = matches.get_one::<String>("model").expect("arg is required");
+                    let name = matches.get_one::<String>("name").map(String::as_str);
+                    let mut changed = catalog.add(model, name, None);
+                    for region in matches.get_many::<String>("region").unwrap_or_default() {
+                        changed |= catalog.add(model, None, Some(region));
+                    }
+                    if changed {
+                        catalog.save(&path)?;
+                    }
+                }
+                _ => unreachable!("subcommand is required"),
+            }
+        }
+        Some(("regions", matches)) => {
+            let mut list = match matches.get_one::<String>("country") {
+                Some(country) => regions::by_country(country),
+                None => regions::REGIONS.iter().collect(),
             };
+            if let Some(query) = matches.get_one::<String>("query") {
+                let found = regions::search(query);
+                list.retain(|r| found.contains(r));
+            }
 
-            let client = Client::new()?;
+            if matches.get_flag("json") {
+                println!("{}", serde_json::to_string_pretty(&list)?);
+            } else {
+                print_regions(&list);
+            }
+        }
+        Some(("download", matches)) => {
+            let model = matches.get_model().expect("arg is required");
+            let imei = matches.get_imei().expect("arg is required");
+            let region = matches.get_region().expect("arg is required");
+            let json = matches.get_flag("json");
+            let policy = Policy::from_matches(matches);
+
+            let client = new_client(matches)?;
             let version = client.fetch_version(model, region).await?;
             let mut nonce = client.generate_nonce().await?;
             let info = client
                 .file_info(model, imei, region, &version, &mut nonce)
                 .await?;
+            remember(model, &info.display_name, region);
 
-            print_info(model, region, &info);
-
-            let resp = client.download(&info, &mut nonce).await?;
+            if !json {
+                let os = OsInfo::from_binary_info(&info);
+                print_info(model, region, &info, &os, false);
+            }
 
             let (filename, decrypt_key) = if matches.get_flag("download-only") {
-                (Cow::from(info.binary_name), None)
+                (Cow::from(info.binary_name.as_str()), None)
             } else {
                 match (
-                    info.decrypt_key,
+                    &info.decrypt_key,
                     info.binary_name.strip_suffix(".enc2"),
                     info.binary_name.strip_suffix(".enc4"),
                 ) {
                     (DecryptKey::V2(key), Some(filename), None)
                     | (DecryptKey::V4(key), None, Some(filename)) => {
-                        (Cow::from(filename), Some(key))
+                        (Cow::from(filename), Some(*key))
                     }
                     (DecryptKey::Unknown, None, None) => {
                         tracing::warn!(
                             "couldn't determine decryption key. falling back to download only."
                         );
-                        (Cow::from(info.binary_name), None)
+                        (Cow::from(info.binary_name.as_str()), None)
                     }
                     _ => unreachable!(),
                 }
             };
 
-            let pb = progress::new(info.binary_size);
-
-            let st = resp
-                .bytes_stream()
-                .map_err(|e| io::Error::new(io::ErrorKind::Other, e));
-            let reader = progress::wrap_reader(StreamReader::new(st), pb.clone());
-            let mut reader = BufReader::new(reader);
+            let dest = match matches.get_one::<PathBuf>("output") {
+                Some(output) if output.is_dir() => output.join(filename.as_ref()),
+                Some(output) => output.clone(),
+                None => PathBuf::from(filename.as_ref()),
+            };
+            let dest = match policy.resolve(&dest)? {
+                output::Target::Write(dest) => dest,
+                output::Target::Skip(dest) => {
+                    print_skipped(&dest, json)?;
+                    return Ok(());
+                }
+            };
 
-            let dest: Cow<'_, Path> = match output {
-                Some(Destination::File(file)) => file.into(),
-                Some(Destination::Dir(dir)) => dir.join(filename.to_string()).into(),
-                None => PathBuf::from(filename.to_string()).into(),
+            // Only the encrypted file can be continued, the decryption always starts over.
+            let part = output::part_path(&dest);
+            let (offset, partial) = match decrypt_key {
+                Some(_) => (0, Partial::Remove),
+                None => match std::fs::metadata(&part) {
+                    Ok(md) if md.len() < info.binary_size => (md.len(), Partial::Keep),
+                    Ok(_) | Err(_) => (0, Partial::Keep),
+                },
             };
 
-            println!("Saving file to {}", dest.display());
-            let out = File::create(dest).await?;
-            let mut writer = BufWriter::new(out);
+            let pb = reporter::new(events.clone(), Phase::Download, info.binary_size);
+            pb.start(Phase::Download, info.binary_size);
+            pb.set_position(offset);
 
-            if let Some(decrypt_key) = decrypt_key {
-                decrypt::decrypt(&decrypt_key, &mut reader, &mut writer).await?;
-            } else {
-                tokio::io::copy(&mut reader, &mut writer).await?;
-            }
+            let saved = output::run(&dest, partial, &pb, async {
+                let resp = client.download_from(&info, &mut nonce, offset).await?;
+
+                let window = matches.get_one::<Window>("window").copied();
+                let on_pause = |paused| match window {
+                    Some(window) if paused => {
+                        pb.set_message(format!("paused until {}", window.start.format("%H:%M")))
+                    }
+                    _ => pb.set_message(""),
+                };
+                let st = schedule::download_from(
+                    &client, &info, &mut nonce, resp, offset, window, on_pause,
+                );
+                let reader = StreamReader::new(Box::pin(st));
+                let reader = match matches.get_one::<u64>("limit-rate") {
+                    Some(rate) => Either::Left(throttle::limit(reader, *rate)),
+                    None => Either::Right(reader),
+                };
+                let reader = progress::wrap_reader(reader, pb.clone());
+                let mut reader = BufReader::new(reader);
+
+                // Status messages go to stderr, stdout is kept for the result.
+                if offset > 0 {
+                    eprintln!("Continuing {} at {}", dest.display(), HumanBytes(offset));
+                } else {
+                    eprintln!("Saving file to {}", dest.display());
+                }
+                let mut out = OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .truncate(offset == 0)
+                    .open(&part)
+                    .await?;
+                out.set_len(offset).await?;
+                out.seek(SeekFrom::Start(offset)).await?;
+                let mut writer = BufWriter::new(out);
+
+                let saved = if let Some(decrypt_key) = decrypt_key {
+                    let output = decrypt::decrypt(&decrypt_key, &mut reader, &mut writer).await?;
+                    Saved {
+                        path: &dest,
+                        size: output.size,
+                        encrypted: Some(output.encrypted),
+                        decrypted: Some(output.decrypted),
+                    }
+                } else {
+                    let hasher = Hasher::new();
+                    if offset > 0 {
+                        let mut done = File::open(&part).await?.take(offset);
+                        hasher.update_from(&mut done).await?;
+                    }
+                    let (size, checksums) =
+                        checksum::copy_with(hasher, &mut reader, &mut writer).await?;
+                    Saved {
+                        path: &dest,
+                        size: offset + size,
+                        encrypted: Some(checksums),
+                        decrypted: None,
+                    }
+                };
+                writer.get_ref().sync_all().await?;
+                output::finish(&dest)?;
+                Ok(saved)
+            })
+            .await?;
 
+            pb.finish(Phase::Download);
             pb.finish_with_message("Download complete");
+            print_saved(&saved, json)?;
         }
         Some(("decrypt", matches)) => {
             let model = matches.get_model().expect("arg is required");
             let imei = matches.get_imei().expect("arg is required");
             let region = matches.get_region().expect("arg is required");
+            let json = matches.get_flag("json");
             let version = matches
                 .get_one::<String>("firmware-version")
                 .expect("arg is required");
@@ -164,23 +438,18 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .get_one::<PathBuf>("input")
                 .expect("arg is required");
 
-            let output = match matches.get_one::<PathBuf>("output") {
-                Some(output) if output.is_dir() => Some(Destination::Dir(output)),
-                Some(output) if !output.exists() => Some(Destination::File(output)),
-                Some(output) if output.exists() => {
-                    println!("Output file {} already exists", output.display());
-                    return Ok(());
-                }
-                Some(_) | None => None,
-            };
+            let policy = Policy::from_matches(matches);
 
-            let client = Client::new()?;
+            let client = new_client(matches)?;
             let mut nonce = client.generate_nonce().await?;
             let info = client
                 .file_info(model, imei, region, version, &mut nonce)
                 .await?;
 
-            print_info(model, region, &info);
+            if !json {
+                let os = OsInfo::from_binary_info(&info);
+                print_info(model, region, &info, &os, false);
+            }
 
             let (filename, decrypt_key) = match (
                 info.decrypt_key,
@@ -192,54 +461,706 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     (PathBuf::from(filename), key.to_vec())
                 }
                 (DecryptKey::Unknown, None, None) => {
-                    println!("couldn't determine decryption key.");
+                    eprintln!("couldn't determine decryption key.");
                     return Ok(());
                 }
                 _ => unreachable!(),
             };
 
-            let dest: Cow<'_, Path> = match output {
-                Some(Destination::File(file)) => file.into(),
-                Some(Destination::Dir(dir)) => dir.join(filename).into(),
-                None => filename.into(),
+            let dest = match matches.get_one::<PathBuf>("output") {
+                Some(output) if output.is_dir() => output.join(filename),
+                Some(output) => output.clone(),
+                None if matches.get_flag("in-place") => match input.extension() {
+                    Some(ext) if ext == "enc2" || ext == "enc4" => input.with_extension(""),
+                    Some(_) | None => input.with_file_name(filename),
+                },
+                None => filename,
+            };
+            // An interrupted in-place decryption continues from its journal. If it was
+            // interrupted after the rename, the decrypted file already exists and is complete.
+            let in_place = matches.get_flag("in-place");
+            let finished = in_place && in_place::is_finished(input, &dest);
+            let dest = if finished {
+                dest
+            } else {
+                match policy.resolve(&dest)? {
+                    output::Target::Write(dest) => dest,
+                    output::Target::Skip(dest) => {
+                        print_skipped(&dest, json)?;
+                        return Ok(());
+                    }
+                }
+            };
+
+            let md = std::fs::metadata(if finished { &dest } else { input })?;
+            let pb = reporter::new(events.clone(), Phase::Decrypt, md.len());
+
+            let partial = if in_place {
+                Partial::Keep
+            } else {
+                Partial::Remove
             };
 
-            println!("Decrypting file to {}", dest.display());
-            let file = File::open(input).await?;
+            let saved = output::run(&dest, partial, &pb, async {
+                let saved = if in_place {
+                    eprintln!("Decrypting file in place to {}", dest.display());
 
-            let md = file.metadata().await?;
-            let pb = progress::new(md.len());
-            let file = progress::wrap_reader(file, pb);
+                    let (src, dst, pb) = (input.clone(), dest.to_path_buf(), pb.clone());
+                    let output = tokio::task::spawn_blocking(move || {
+                        in_place::decrypt_in_place(&decrypt_key, &src, &dst, pb)
+                    })
+                    .await??;
+                    Saved {
+                        path: &dest,
+                        size: output.size,
+                        encrypted: output.encrypted,
+                        decrypted: Some(output.decrypted),
+                    }
+                } else {
+                    eprintln!("Decrypting file to {}", dest.display());
 
-            let mut reader = BufReader::new(file);
+                    let part = output::part_path(&dest);
+                    let output = if decrypt::can_map(&md) {
+                        let (src, dst, pb) = (input.clone(), part, pb.clone());
+                        tokio::task::spawn_blocking(move || {
+                            decrypt::decrypt_file(&decrypt_key, &src, &dst, pb)
+                        })
+                        .await??
+                    } else {
+                        // Pipes and special files can't be mapped, they're streamed.
+                        pb.start(Phase::Decrypt, md.len());
+                        let reader = File::open(input).await?;
+                        let mut reader = BufReader::new(progress::wrap_reader(reader, pb.clone()));
+                        let mut writer = BufWriter::new(File::create(&part).await?);
+                        let output =
+                            decrypt::decrypt(&decrypt_key, &mut reader, &mut writer).await?;
+                        writer.get_ref().sync_all().await?;
+                        pb.finish(Phase::Decrypt);
+                        output
+                    };
+                    output::finish(&dest)?;
+                    Saved {
+                        path: &dest,
+                        size: output.size,
+                        encrypted: Some(output.encrypted),
+                        decrypted: Some(output.decrypted),
+                    }
+                };
+                Ok(saved)
+            })
+            .await?;
 
-            let out = File::create(dest).await?;
-            let mut writer = BufWriter::new(out);
+            pb.finish_with_message("Decryption complete");
+            print_saved(&saved, json)?;
+        }
+        Some(("serve", matches)) => {
+            let listen = *matches
+                .get_one::<SocketAddr>("listen")
+                .expect("arg has default");
+            let token = matches.get_one::<String>("token").cloned();
+            if token.is_none() && !listen.ip().is_loopback() {
+                return Err(format!("--token is required to listen on {listen}").into());
+            }
 
-            decrypt::decrypt(&decrypt_key, &mut reader, &mut writer).await?;
+            let client = new_client(matches)?;
+            serve::run(client, listen, token).await?;
+        }
+        Some(("completions", matches)) => {
+            let shell = *matches.get_one::<Shell>("shell").expect("arg is required");
+            completions::generate(shell, &mut app, &mut io::stdout())?;
+        }
+        Some(("complete-values", matches)) => {
+            let kind = matches.get_one::<String>("kind").expect("arg is required");
+            completions::print_values(kind, &mut io::stdout())?;
         }
+        Some(("manpage", matches)) => match matches.get_one::<PathBuf>("dir") {
+            Some(dir) => {
+                std::fs::create_dir_all(dir)?;
+                clap_mangen::generate_to(app, dir)?;
+            }
+            None => clap_mangen::Man::new(app).render(&mut io::stdout())?,
+        },
         _ => {}
     }
 
     Ok(())
 }
 
-enum Destination<'a> {
-    Dir(&'a PathBuf),
-    File(&'a PathBuf),
+fn app() -> Command {
+    Command::new(crate_name!())
+        .about(crate_description!())
+        .version(crate_version!())
+        .arg_required_else_help(true)
+        .arg(
+            opt("config", "path to the config file")
+                .value_name("FILE")
+                .value_parser(clap::value_parser!(PathBuf))
+                .global(true),
+        )
+        .args_logging()
+        .args_client()
+        .args_profile()
+        .subcommand(
+            Command::new("check")
+                .about("check for the lastest available firmware version")
+                .args_model_imei_region()
+                .arg(
+                    opt("all", "also print the unknown fields of the response")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(opt("json", "print the firmware info as JSON").action(ArgAction::SetTrue)),
+        )
+        .subcommand(
+            Command::new("changelog")
+                .about("show the changelog of a model")
+                .args_model_region()
+                .arg(
+                    opt("firmware-version", "only show the changelog of a version")
+                        .short('v')
+                        .value_name("VERSION"),
+                )
+                .arg(opt("json", "print the changelog as JSON").action(ArgAction::SetTrue))
+                .arg(opt("changelog-url", "base url of the changelog pages").value_name("URL")),
+        )
+        .subcommand(
+            Command::new("diff")
+                .about("compare two firmwares")
+                .arg(required_opt("imei", "IMEI/serial number").value_name("IMEI"))
+                .arg(
+                    Arg::new("from")
+                        .help("firmware as MODEL:REGION[:VERSION], the latest by default")
+                        .value_name("FROM")
+                        .value_parser(parse_target)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("to")
+                        .help("firmware to compare with")
+                        .value_name("TO")
+                        .value_parser(parse_target)
+                        .required(true),
+                )
+                .arg(
+                    opt("from-firmware", "decrypted firmware or AP tarball of FROM")
+                        .value_name("FILE")
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .requires("to-firmware"),
+                )
+                .arg(
+                    opt("to-firmware", "decrypted firmware or AP tarball of TO")
+                        .value_name("FILE")
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .requires("from-firmware"),
+                )
+                .arg(opt("json", "print the differences as JSON").action(ArgAction::SetTrue)),
+        )
+        .subcommand(
+            Command::new("scan")
+                .about("find the latest firmware of a model in all known regions")
+                .arg(model_arg().required(true))
+                .arg(
+                    opt(
+                        "imei",
+                        "also fetch the OS and size with this IMEI/serial number",
+                    )
+                    .value_name("IMEI"),
+                )
+                .arg(
+                    opt("regions", "only scan these regions")
+                        .short('r')
+                        .value_name("REGION,...")
+                        .value_delimiter(',')
+                        .value_hint(ValueHint::Other),
+                )
+                .arg(
+                    opt("country", "only scan the regions of a country")
+                        .value_name("COUNTRY")
+                        .conflicts_with("regions"),
+                )
+                .arg(
+                    opt("jobs", "number of concurrent requests")
+                        .short('j')
+                        .value_name("N")
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("8"),
+                )
+                .arg(opt("json", "print the results as JSON").action(ArgAction::SetTrue)),
+        )
+        .subcommand(
+            Command::new("regions")
+                .about("list the known regions (CSC codes)")
+                .arg(
+                    opt(
+                        "country",
+                        "only list the regions of a country (ISO code or name)",
+                    )
+                    .value_name("COUNTRY"),
+                )
+                .arg(opt("json", "print the regions as JSON").action(ArgAction::SetTrue))
+                .arg(Arg::new("query").help("search the code, country, carrier or group")),
+        )
+        .subcommand(
+            Command::new("models")
+                .about("search the local catalog of models")
+                .long_about(
+                    "search the local catalog of models\n\n\
+                     The catalog is built up from the models and regions of `check`, \
+                     `download` and `diff`.",
+                )
+                .subcommand_required(true)
+                .disable_help_subcommand(true)
+                .subcommand(
+                    Command::new("search")
+                        .about("find models by marketing name or model number")
+                        .arg(
+                            Arg::new("query")
+                                .help("e.g. \"Galaxy S23\" or S911")
+                                .value_name("QUERY")
+                                .num_args(1..)
+                                .required(true),
+                        )
+                        .arg(opt("json", "print the models as JSON").action(ArgAction::SetTrue)),
+                )
+                .subcommand(
+                    Command::new("list")
+                        .about("list all models of the catalog")
+                        .arg(opt("json", "print the models as JSON").action(ArgAction::SetTrue)),
+                )
+                .subcommand(
+                    Command::new("add")
+                        .about("add a model to the catalog")
+                        .arg(Arg::new("model").value_name("MODEL").required(true))
+                        .arg(Arg::new("name").value_name("NAME").help("marketing name"))
+                        .arg(
+                            opt("region", "a region of the model")
+                                .short('r')
+                                .value_name("REGION")
+                                .action(ArgAction::Append),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("download")
+                .about("download the latest firmware")
+                .args_model_imei_region()
+                .arg(
+                    opt("download-only", "don't decrypt the firmware file")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(opt("json", "print the checksums as JSON").action(ArgAction::SetTrue))
+                .args_output_policy()
+                .arg(
+                    opt("limit-rate", "limit the download speed, e.g. 500K or 5M")
+                        .value_name("RATE")
+                        .value_parser(parse_rate),
+                )
+                .arg(
+                    opt(
+                        "window",
+                        "only download in a daily time window, e.g. 22:00-06:00",
+                    )
+                    .value_name("HH:MM-HH:MM")
+                    .value_parser(clap::value_parser!(Window)),
+                )
+                .arg(
+                    path_arg("output", "output to a specific file or directory")
+                        .value_name("OUTPUT"),
+                ),
+        )
+        .subcommand(
+            Command::new("decrypt")
+                .about("decrypt a downloaded firmware")
+                .args_model_imei_region()
+                .arg(
+                    required_opt("firmware-version", "")
+                        .short('v')
+                        .value_name("VERSION"),
+                )
+                .arg(
+                    opt("in-place", "decrypt the input file in place")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with("output"),
+                )
+                .arg(opt("json", "print the checksums as JSON").action(ArgAction::SetTrue))
+                .args_output_policy()
+                .arg(required_path_arg("input", "path to encrypted firmware").value_name("INPUT"))
+                .arg(
+                    path_arg("output", "output to a specific file or directory")
+                        .value_name("OUTPUT"),
+                ),
+        )
+        .subcommand(
+            Command::new("serve")
+                .about("serve an HTTP API for firmware lookups and downloads")
+                .arg(
+                    opt("listen", "address to listen on")
+                        .value_name("ADDR")
+                        .value_parser(clap::value_parser!(SocketAddr))
+                        .default_value("127.0.0.1:8080"),
+                )
+                .arg(
+                    opt("token", "token that clients have to send as bearer token")
+                        .value_name("TOKEN")
+                        .value_parser(NonEmptyStringValueParser::new())
+                        .env("FRIGG_TOKEN")
+                        .hide_env_values(true),
+                ),
+        )
+        .subcommand(
+            Command::new("completions")
+                .about("print the shell completions")
+                .long_about(
+                    "print the shell completions\n\n\
+                     In bash, zsh and fish models and regions are completed with the local \
+                     model catalog and the bundled regions, which are read while completing.",
+                )
+                .arg(
+                    Arg::new("shell")
+                        .value_name("SHELL")
+                        .value_parser(clap::value_parser!(Shell))
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            Command::new("complete-values")
+                .about("print the models or regions for the shell completions")
+                .hide(true)
+                .arg(
+                    Arg::new("kind")
+                        .value_name("KIND")
+                        .value_parser(["model", "region"])
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            Command::new("manpage").about("print the man page").arg(
+                opt("dir", "write the pages of all subcommands to DIR")
+                    .value_name("DIR")
+                    .value_parser(clap::value_parser!(PathBuf)),
+            ),
+        )
+}
+
+fn new_client(matches: &ArgMatches) -> Result<Client, Error> {
+    let config = Config::load(matches.get_one::<PathBuf>("config").map(PathBuf::as_path))?;
+    let mut network = config.network;
+    network.update_from(matches);
+
+    let mut builder = network.client_builder()?;
+    if let Some(dir) = matches.get_one::<PathBuf>("record") {
+        builder = builder.record(record::Mode::Record(dir.clone()));
+    }
+    if let Some(dir) = matches.get_one::<PathBuf>("replay") {
+        builder = builder.record(record::Mode::Replay(dir.clone()));
+    }
+
+    let mut profile = matches
+        .get_one::<Profile>("profile")
+        .cloned()
+        .unwrap_or_default();
+    if let Some(binary_nature) = matches.get_one::<BinaryNature>("binary-nature") {
+        profile = profile.binary_nature(*binary_nature);
+    }
+    for (name, value) in matches
+        .get_many::<(String, String)>("fus-field")
+        .unwrap_or_default()
+    {
+        profile = profile.field(name, value);
+    }
+
+    if let Ok(Some(url)) = matches.try_get_one::<String>("changelog-url") {
+        builder = builder.changelog_base_url(url);
+    }
+
+    builder.profile(profile).build()
 }
 
-fn print_info(model: &str, region: &str, info: &BinaryInfo) {
+fn print_info(model: &str, region: &str, info: &BinaryInfo, os: &OsInfo, all_fields: bool) {
     println!("Name: {}", info.display_name);
     println!("Model: {model}");
     println!("Region: {region}");
     println!("Latest Version:");
     println!("  Version: {}", info.version);
+    let (pda, csc, phone) = info.version_parts();
+    println!("    PDA: {pda}");
+    if let Some(csc) = csc {
+        println!("    CSC: {csc}");
+    }
+    if let Some(phone) = phone {
+        println!("    Phone: {phone}");
+    }
     println!("  OS: {}", info.os_version);
+    match (&os.android_version, os.api_level) {
+        (Some(version), Some(api_level)) => println!("  Android: {version} (API {api_level})"),
+        (Some(version), None) => println!("  Android: {version}"),
+        (None, _) => {}
+    }
+    if let Some(security_patch) = &os.security_patch {
+        println!("  Security patch: {security_patch}");
+    }
+    for (name, value) in [
+        ("Binary OS", &info.binary_os_version),
+        ("Platform", &info.platform),
+        ("Release date", &info.release_date),
+        ("Update date", &info.update_date),
+    ] {
+        if let Some(value) = value {
+            println!("  {name}: {value}");
+        }
+    }
     println!("  Filename: {}", info.binary_name);
     println!("  Size: {} bytes", info.binary_size);
+    if let Some(crc) = &info.crc {
+        println!("  CRC: {crc}");
+    }
     match info.decrypt_key {
         DecryptKey::V2(key) | DecryptKey::V4(key) => println!("  Decrypt key: {key:02X}"),
         DecryptKey::Unknown => println!("  Decrypt key is unknown"),
     }
+    if let Some(url) = &info.description_url {
+        println!("  Changelog: {url}");
+    }
+    if all_fields && !info.extra.is_empty() {
+        println!("Other fields:");
+        for (name, value) in &info.extra {
+            println!("  {name}: {value}");
+        }
+    }
+}
+
+fn print_diff(diff: &Diff) {
+    let label = |side: &DiffSide| format!("{}/{}", side.model, side.region);
+    let mark = |changed: bool| if changed { "changed" } else { "" }.to_owned();
+
+    let mut rows = diff
+        .components
+        .iter()
+        .map(|c| {
+            [
+                c.component.to_owned(),
+                c.from.clone(),
+                c.to.clone(),
+                mark(c.changed),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    rows.push([
+        "Size".to_owned(),
+        HumanBytes(diff.from.size).to_string(),
+        HumanBytes(diff.to.size).to_string(),
+        size_delta(diff.size_delta),
+    ]);
+    print_table(["", &label(&diff.from), &label(&diff.to), ""], &rows);
+
+    if let Some(partitions) = &diff.partitions {
+        println!();
+        let rows = partitions
+            .iter()
+            .map(|p| {
+                [
+                    p.name.clone(),
+                    p.status.to_string(),
+                    size_delta(p.size_delta),
+                ]
+            })
+            .collect::<Vec<_>>();
+        print_table(["Partition", "Status", "Size"], &rows);
+    }
+}
+
+fn size_delta(delta: i64) -> String {
+    match delta {
+        0 => "±0".to_owned(),
+        d if d > 0 => format!("+{}", HumanBytes(d.unsigned_abs())),
+        d => format!("-{}", HumanBytes(d.unsigned_abs())),
+    }
+}
+
+fn print_scan(results: &[Found]) {
+    let rows = results
+        .iter()
+        .filter_map(|found| {
+            let version = found.version.clone()?;
+            Some([
+                found.region.clone(),
+                version,
+                found.os_version.clone().unwrap_or_else(|| "-".to_owned()),
+                found
+                    .size
+                    .map_or_else(|| "-".to_owned(), |size| HumanBytes(size).to_string()),
+            ])
+        })
+        .collect::<Vec<_>>();
+    print_table(["Region", "Version", "OS", "Size"], &rows);
+
+    let missing = results.len() - rows.len();
+    if missing > 0 {
+        println!(
+            "No firmware found in {missing} of {} regions",
+            results.len()
+        );
+    }
+}
+
+fn print_models(list: &[&Model]) {
+    let rows = list
+        .iter()
+        .map(|m| {
+            let regions = m.regions.iter().cloned().collect::<Vec<_>>();
+            [
+                m.model.clone(),
+                m.name.clone().unwrap_or_else(|| "-".to_owned()),
+                regions.join(","),
+            ]
+        })
+        .collect::<Vec<_>>();
+    print_table(["Model", "Name", "Regions"], &rows);
+}
+
+fn print_regions(list: &[&Region]) {
+    let rows = list
+        .iter()
+        .map(|r| {
+            [
+                r.code.to_owned(),
+                r.country.to_owned(),
+                r.carrier.unwrap_or("-").to_owned(),
+                r.group.unwrap_or("-").to_owned(),
+            ]
+        })
+        .collect::<Vec<_>>();
+    print_table(["Code", "Country", "Carrier", "Group"], &rows);
+}
+
+/// Adds the model with its display name and region to the local model catalog.
+fn remember(model: &str, name: &str, region: &str) {
+    let Some(path) = models::default_path() else {
+        return;
+    };
+    let result = Catalog::load(&path).and_then(|mut catalog| {
+        if catalog.add(model, Some(name), Some(region)) {
+            catalog.save(&path)?;
+        }
+        Ok(())
+    });
+    if let Err(e) = result {
+        tracing::warn!("failed to update the model catalog: {e:#}");
+    }
+}
+
+fn print_table<const N: usize>(header: [&str; N], rows: &[[String; N]]) {
+    let mut widths = header.map(str::len);
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let print_row = |cells: Vec<&str>| {
+        let line = cells
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+        println!("{}", line.trim_end());
+    };
+    print_row(header.to_vec());
+    for row in rows {
+        print_row(row.iter().map(String::as_str).collect());
+    }
+}
+
+fn print_changelog(entries: &[Entry]) {
+    for (i, entry) in entries.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!("Version: {}", entry.version);
+        for (name, value) in [
+            ("Date", &entry.date),
+            ("Android", &entry.android_version),
+            ("Security patch", &entry.security_patch),
+        ] {
+            if let Some(value) = value {
+                println!("  {name}: {value}");
+            }
+        }
+        if !entry.notes.is_empty() {
+            println!("  Notes:");
+            for note in &entry.notes {
+                println!("    - {note}");
+            }
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Diff {
+    from: DiffSide,
+    to: DiffSide,
+    components: Vec<ComponentChange>,
+    size_delta: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    partitions: Option<Vec<PartitionChange>>,
+}
+
+#[derive(Serialize)]
+struct DiffSide {
+    model: String,
+    region: String,
+    version: String,
+    size: u64,
+}
+
+#[derive(Serialize)]
+struct Check<'a> {
+    model: &'a str,
+    region: &'a str,
+    #[serde(flatten)]
+    info: &'a BinaryInfo,
+    #[serde(flatten)]
+    os: &'a OsInfo,
+}
+
+#[derive(Serialize)]
+struct Saved<'a> {
+    path: &'a Path,
+    size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    encrypted: Option<Checksums>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    decrypted: Option<Checksums>,
+}
+
+fn print_skipped(path: &Path, json: bool) -> Result<(), Error> {
+    if json {
+        let skipped = serde_json::json!({ "path": path, "skipped": true });
+        println!("{}", serde_json::to_string_pretty(&skipped)?);
+    } else {
+        println!("Skipping {}, it already exists", path.display());
+    }
+    Ok(())
+}
+
+fn print_saved(saved: &Saved<'_>, json: bool) -> Result<(), Error> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(saved)?);
+        return Ok(());
+    }
+
+    println!("File: {}", saved.path.display());
+    println!("  Size: {} bytes", saved.size);
+    for (name, checksums) in [
+        ("Encrypted", &saved.encrypted),
+        ("Decrypted", &saved.decrypted),
+    ] {
+        if let Some(checksums) = checksums {
+            println!("  {name}:");
+            println!("    MD5: {}", checksums.md5);
+            println!("    SHA-1: {}", checksums.sha1);
+            println!("    SHA-256: {}", checksums.sha256);
+        }
+    }
+    Ok(())
 }
@@ -0,0 +1,178 @@
+//! Local catalog of model numbers, their marketing names and known regions.
+//!
+//! The catalog is built up from the responses of the FUS server, which contain
+//! the display name of a model, and is stored as JSON in the user's data directory.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Model {
+    pub model: String,
+    /// The marketing name like `Galaxy S23`.
+    pub name: Option<String>,
+    pub regions: BTreeSet<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Catalog {
+    /// Sorted by model number.
+    models: Vec<Model>,
+}
+
+pub fn default_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("frigg").join("models.json"))
+}
+
+impl Catalog {
+    /// Loads the catalog from `path`, a missing file is an empty catalog.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content)
+                .with_context(|| format!("invalid model catalog {}", path.display())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => {
+                Err(Error::new(e)
+                    .context(format!("failed to read model catalog {}", path.display())))
+            }
+        }
+    }
+
+    /// Writes the catalog to a temporary file first, so that it's never left half written.
+    /// The temporary file is named after the process so that concurrent runs don't mix
+    /// their writes, the last rename wins.
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create directory {}", dir.display()))?;
+        }
+        let tmp = path.with_extension(format!("json.{}.tmp", std::process::id()));
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("failed to write {}", tmp.display()))?;
+        fs::rename(&tmp, path)
+            .with_context(|| format!("failed to write model catalog {}", path.display()))?;
+        Ok(())
+    }
+
+    pub fn models(&self) -> &[Model] {
+        &self.models
+    }
+
+    pub fn get(&self, model: &str) -> Option<&Model> {
+        self.models
+            .iter()
+            .find(|m| m.model.eq_ignore_ascii_case(model))
+    }
+
+    /// Adds a model or updates its name and regions. Returns whether anything changed.
+    pub fn add(&mut self, model: &str, name: Option<&str>, region: Option<&str>) -> bool {
+        let model = model.trim().to_ascii_uppercase();
+        let (index, mut changed) = match self.models.binary_search_by(|m| m.model.cmp(&model)) {
+            Ok(index) => (index, false),
+            Err(index) => {
+                let entry = Model {
+                    model,
+                    name: None,
+                    regions: BTreeSet::new(),
+                };
+                self.models.insert(index, entry);
+                (index, true)
+            }
+        };
+
+        let entry = &mut self.models[index];
+        if let Some(name) = name.map(str::trim).filter(|n| !n.is_empty()) {
+            if entry.name.as_deref() != Some(name) {
+                entry.name = Some(name.to_owned());
+                changed = true;
+            }
+        }
+        if let Some(region) = region {
+            changed |= entry.regions.insert(region.to_ascii_uppercase());
+        }
+        changed
+    }
+
+    /// Finds the models whose number contains `query` or whose name contains all
+    /// words of `query` at the start of a word, e.g. `galaxy s23` finds the
+    /// `Galaxy S23` and the `Galaxy S23 Ultra`. Exact matches come first.
+    pub fn search(&self, query: &str) -> Vec<&Model> {
+        let query = query.trim().to_lowercase();
+        let words = query.split_whitespace().collect::<Vec<_>>();
+        if words.is_empty() {
+            return Vec::new();
+        }
+
+        let mut found = self
+            .models
+            .iter()
+            .filter(|m| {
+                let name = m.name.as_deref().unwrap_or_default().to_lowercase();
+                let name_words = name.split_whitespace().collect::<Vec<_>>();
+                m.model.to_lowercase().contains(&query)
+                    || words
+                        .iter()
+                        .all(|w| name_words.iter().any(|n| n.starts_with(w)))
+            })
+            .collect::<Vec<_>>();
+        found.sort_by_cached_key(|m| {
+            let name = m.name.as_deref().unwrap_or_default().to_lowercase();
+            let exact = name == query || m.model.eq_ignore_ascii_case(&query);
+            (!exact, name.len())
+        });
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_and_search() {
+        let mut catalog = Catalog::default();
+        assert!(catalog.add("SM-S918B", Some("Galaxy S23 Ultra"), Some("EUX")));
+        assert!(catalog.add("sm-s911b", Some("Galaxy S23"), Some("eux")));
+        assert!(catalog.add("SM-S911B", None, Some("DBT")));
+        assert!(!catalog.add("SM-S911B", Some("Galaxy S23"), Some("EUX")));
+        assert!(catalog.add("SM-G991B", Some("Galaxy S21 5G"), None));
+
+        let s23 = catalog.get("sm-s911b").unwrap();
+        assert_eq!(s23.name.as_deref(), Some("Galaxy S23"));
+        assert_eq!(s23.regions.iter().collect::<Vec<_>>(), ["DBT", "EUX"]);
+
+        fn models(found: Vec<&Model>) -> Vec<&str> {
+            found.iter().map(|m| m.model.as_str()).collect()
+        }
+        assert_eq!(
+            models(catalog.search("Galaxy S23")),
+            ["SM-S911B", "SM-S918B"]
+        );
+        assert_eq!(models(catalog.search("s23 ultra")), ["SM-S918B"]);
+        assert_eq!(models(catalog.search("G991")), ["SM-G991B"]);
+        assert_eq!(models(catalog.search("galaxy s2 ultra")), ["SM-S918B"]);
+        assert!(catalog.search("Galaxy A54").is_empty());
+    }
+
+    #[test]
+    fn load_and_save() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("frigg").join("models.json");
+        assert!(Catalog::load(&path).unwrap().models().is_empty());
+
+        let mut catalog = Catalog::default();
+        catalog.add("SM-S911B", Some("Galaxy S23"), Some("EUX"));
+        catalog.save(&path).unwrap();
+        assert_eq!(Catalog::load(&path).unwrap().models(), catalog.models());
+        let files = fs::read_dir(path.parent().unwrap()).unwrap().count();
+        assert_eq!(files, 1);
+    }
+}
@@ -0,0 +1,169 @@
+//! Security patch level and Android version of a firmware.
+//!
+//! The values are taken from the FUS response or the changelog page of the
+//! firmware, or from a `build.prop`.
+
+use chrono::NaiveDate;
+use serde::Serialize;
+
+use crate::binary_info::BinaryInfo;
+use crate::changelog;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct OsInfo {
+    /// The security patch level like `2022-04-01`.
+    pub security_patch: Option<String>,
+    pub android_version: Option<String>,
+    pub api_level: Option<u32>,
+}
+
+impl OsInfo {
+    /// Takes the Android version from `CURRENT_OS_VERSION` (e.g. `S(Android 12)`)
+    /// and the patch level from a `*SECURITY_PATCH*` field if the response has one.
+    pub fn from_binary_info(info: &BinaryInfo) -> Self {
+        let android_version = info
+            .os_version
+            .split_once("Android ")
+            .map(|(_, v)| v.trim_end_matches(')').trim().to_owned())
+            .filter(|v| !v.is_empty());
+        let security_patch = info
+            .extra
+            .iter()
+            .filter(|(name, _)| name.contains("SECURITY_PATCH"))
+            .find_map(|(_, value)| parse_patch_level(value));
+
+        Self::new(security_patch, android_version, None)
+    }
+
+    /// Takes the security patch level of the first (i.e. latest) firmware on the changelog page.
+    pub fn from_changelog(html: &str) -> Self {
+        let security_patch = changelog::parse(html)
+            .into_iter()
+            .find_map(|entry| entry.security_patch.as_deref().and_then(parse_patch_level));
+
+        Self::new(security_patch, None, None)
+    }
+
+    pub fn from_build_prop(content: &str) -> Self {
+        let mut security_patch = None;
+        let mut android_version = None;
+        let mut api_level = None;
+        for line in content.lines() {
+            match line.trim().split_once('=') {
+                Some(("ro.build.version.security_patch", value)) => {
+                    security_patch = parse_patch_level(value);
+                }
+                Some(("ro.build.version.release", value)) => {
+                    android_version = Some(value.trim().to_owned());
+                }
+                Some(("ro.build.version.sdk", value)) => api_level = value.trim().parse().ok(),
+                _ => {}
+            }
+        }
+        Self::new(security_patch, android_version, api_level)
+    }
+
+    /// Fills the missing values from `other`.
+    pub fn or(self, other: Self) -> Self {
+        Self::new(
+            self.security_patch.or(other.security_patch),
+            self.android_version.or(other.android_version),
+            self.api_level.or(other.api_level),
+        )
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.security_patch.is_some() && self.android_version.is_some() && self.api_level.is_some()
+    }
+
+    fn new(
+        security_patch: Option<String>,
+        android_version: Option<String>,
+        api_level: Option<u32>,
+    ) -> Self {
+        let api_level = api_level.or_else(|| android_version.as_deref().and_then(api_level_of));
+        Self {
+            security_patch,
+            android_version,
+            api_level,
+        }
+    }
+}
+
+/// Accepts `2022-04-01`, `2022-04` and `B(2022-04)` like values.
+fn parse_patch_level(s: &str) -> Option<String> {
+    let s = s.trim().trim_end_matches(')');
+    let s = s.rsplit('(').next().unwrap_or(s).trim();
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .or_else(|_| NaiveDate::parse_from_str(&format!("{s}-01"), "%Y-%m-%d"))
+        .ok()
+        .map(|date| date.format("%Y-%m-%d").to_string())
+}
+
+fn api_level_of(android_version: &str) -> Option<u32> {
+    let level = match android_version {
+        "5.0" | "5" => 21,
+        "5.1" => 22,
+        "6.0" | "6" => 23,
+        "7.0" | "7" => 24,
+        "7.1" => 25,
+        "8.0" | "8" => 26,
+        "8.1" => 27,
+        "9" => 28,
+        "10" => 29,
+        "11" => 30,
+        "12" => 31,
+        "12L" => 32,
+        "13" => 33,
+        "14" => 34,
+        "15" => 35,
+        "16" => 36,
+        _ => return None,
+    };
+    Some(level)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patch_levels() {
+        assert_eq!(
+            parse_patch_level("2022-04-01").as_deref(),
+            Some("2022-04-01")
+        );
+        assert_eq!(
+            parse_patch_level("B(2022-04)").as_deref(),
+            Some("2022-04-01")
+        );
+        assert_eq!(parse_patch_level("April 2022"), None);
+    }
+
+    #[test]
+    fn changelog() {
+        let html = "<div class='row'><p>Version : G991BXXU5CVDD</p>\
+            <p>Security patch level: <span>2022-04-01</span></p></div>\
+            <div class='row'><p>Security patch level: 2022-03-01</p></div>";
+        let info = OsInfo::from_changelog(html);
+        assert_eq!(info.security_patch.as_deref(), Some("2022-04-01"));
+    }
+
+    #[test]
+    fn build_prop() {
+        let content = "# begin build properties\n\
+            ro.build.version.sdk=33\n\
+            ro.build.version.release=13\n\
+            ro.build.version.security_patch=2023-02-01\n";
+        let info = OsInfo::from_build_prop(content);
+        assert_eq!(
+            info,
+            OsInfo {
+                security_patch: Some("2023-02-01".to_owned()),
+                android_version: Some("13".to_owned()),
+                api_level: Some(33),
+            }
+        );
+        assert!(info.is_complete());
+    }
+}
@@ -0,0 +1,197 @@
+//! What `download` and `decrypt` do when the output file already exists.
+//!
+//! The output is written to a `.part` file next to the destination, which is only
+//! renamed to the destination once the file is complete. If writing fails or is
+//! interrupted with Ctrl-C, the `.part` file is removed or kept to be continued.
+
+use std::ffi::OsString;
+use std::fs;
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use anyhow::anyhow;
+use clap::ArgMatches;
+use indicatif::ProgressBar;
+
+use frigg::Error;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Policy {
+    /// Refuse to replace an existing file.
+    #[default]
+    Fail,
+    Overwrite,
+    Skip,
+    /// Write to `name (1).ext` or the next free name instead.
+    Rename,
+}
+
+/// What happens to the `.part` file if writing the output fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Partial {
+    /// Remove it, the output has to be written from the start.
+    Remove,
+    /// Keep it, the next run continues where it stopped.
+    Keep,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Target {
+    Write(PathBuf),
+    Skip(PathBuf),
+}
+
+impl Policy {
+    pub fn from_matches(matches: &ArgMatches) -> Self {
+        if matches.get_flag("overwrite") {
+            Self::Overwrite
+        } else if matches.get_flag("skip-existing") {
+            Self::Skip
+        } else if matches.get_flag("rename") {
+            Self::Rename
+        } else {
+            Self::Fail
+        }
+    }
+
+    /// Decides where to write the output for `path`.
+    pub fn resolve(self, path: &Path) -> Result<Target, Error> {
+        if !path.exists() {
+            return Ok(Target::Write(path.to_path_buf()));
+        }
+        match self {
+            Self::Fail => Err(anyhow!(
+                "{} already exists, use --overwrite, --skip-existing or --rename",
+                path.display()
+            )),
+            Self::Overwrite => Ok(Target::Write(path.to_path_buf())),
+            Self::Skip => Ok(Target::Skip(path.to_path_buf())),
+            Self::Rename => Ok(Target::Write(free_name(path))),
+        }
+    }
+}
+
+/// Returns the temporary file the output of `path` is written to.
+pub fn part_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(".part");
+    path.with_file_name(name)
+}
+
+/// Moves the finished `.part` file of `path` to its final name.
+pub fn finish(path: &Path) -> io::Result<()> {
+    fs::rename(part_path(path), path)
+}
+
+/// Runs `work` that writes the output of `path` and cleans up if it fails.
+///
+/// On Ctrl-C the work is dropped and the process exits after the cleanup.
+pub async fn run<T, F>(path: &Path, partial: Partial, pb: &ProgressBar, work: F) -> Result<T, Error>
+where
+    F: Future<Output = Result<T, Error>>,
+{
+    tokio::select! {
+        result = work => {
+            if result.is_err() {
+                pb.abandon();
+                cleanup(path, partial);
+            }
+            result
+        }
+        _ = tokio::signal::ctrl_c() => {
+            pb.abandon_with_message("Interrupted");
+            cleanup(path, partial);
+            if partial == Partial::Keep {
+                eprintln!("Run the same command again to continue");
+            }
+            // Blocking tasks would keep the runtime from shutting down.
+            process::exit(130);
+        }
+    }
+}
+
+fn cleanup(path: &Path, partial: Partial) {
+    if partial == Partial::Keep {
+        return;
+    }
+    let part = part_path(path);
+    match fs::remove_file(&part) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => tracing::warn!("failed to remove {}: {e}", part.display()),
+    }
+}
+
+/// Appends ` (1)`, ` (2)`, ... to the file stem until the name isn't taken.
+fn free_name(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = path.extension().map(|ext| ext.to_string_lossy());
+    (1..)
+        .map(|i| {
+            let name = match &ext {
+                Some(ext) => format!("{stem} ({i}).{ext}"),
+                None => format!("{stem} ({i})"),
+            };
+            path.with_file_name(name)
+        })
+        .find(|path| !path.exists() && !part_path(path).exists())
+        .expect("a free file name")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policies() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("firmware.zip");
+        let new = dir.path().join("new.zip");
+        fs::write(&path, b"").unwrap();
+        fs::write(dir.path().join("firmware (1).zip"), b"").unwrap();
+
+        assert_eq!(Policy::Fail.resolve(&new).unwrap(), Target::Write(new));
+        assert!(Policy::Fail.resolve(&path).is_err());
+        assert_eq!(
+            Policy::Overwrite.resolve(&path).unwrap(),
+            Target::Write(path.clone())
+        );
+        assert_eq!(
+            Policy::Skip.resolve(&path).unwrap(),
+            Target::Skip(path.clone())
+        );
+        assert_eq!(
+            Policy::Rename.resolve(&path).unwrap(),
+            Target::Write(dir.path().join("firmware (2).zip"))
+        );
+        assert_eq!(part_path(&path), dir.path().join("firmware.zip.part"));
+    }
+
+    #[tokio::test]
+    async fn cleanup_on_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("firmware.zip");
+        let pb = ProgressBar::hidden();
+
+        for partial in [Partial::Keep, Partial::Remove] {
+            let result = run(&path, partial, &pb, async {
+                fs::write(part_path(&path), b"part")?;
+                Err::<(), _>(anyhow!("Unpad"))
+            })
+            .await;
+            assert!(result.is_err());
+            assert_eq!(part_path(&path).exists(), partial == Partial::Keep);
+        }
+
+        let written = run(&path, Partial::Remove, &pb, async {
+            fs::write(part_path(&path), b"done")?;
+            finish(&path)?;
+            Ok(())
+        })
+        .await;
+        assert!(written.is_ok());
+        assert_eq!(fs::read(&path).unwrap(), b"done");
+    }
+}
@@ -0,0 +1,143 @@
+//! The fields that identify the client in `NF_DownloadBinaryInform.do` requests.
+//!
+//! Samsung's clients send slightly different requests and some firmwares are
+//! only listed for one of them.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Which binary of a firmware the FUS server should return.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BinaryNature {
+    /// The full firmware that is flashed in the factory.
+    #[default]
+    Factory,
+    /// The firmware that is installed by the Smart Switch/Kies clients
+    /// without wiping the user data.
+    Home,
+}
+
+impl BinaryNature {
+    pub fn value(self) -> &'static str {
+        match self {
+            Self::Factory => "1",
+            Self::Home => "0",
+        }
+    }
+}
+
+impl FromStr for BinaryNature {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "factory" | "1" => Ok(Self::Factory),
+            "home" | "0" => Ok(Self::Home),
+            _ => Err(format!(
+                "invalid binary nature `{s}`, expected factory (1) or home (0)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for BinaryNature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Factory => f.write_str("factory"),
+            Self::Home => f.write_str("home"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    pub access_mode: String,
+    pub binary_nature: BinaryNature,
+    pub client_product: String,
+    /// Additional fields like `DEVICE_CONTENTS_DATA_VERSION` that are sent as they are.
+    pub extra: Vec<(String, String)>,
+}
+
+impl Profile {
+    /// The request of the Smart Switch client.
+    pub fn smart_switch() -> Self {
+        Self {
+            access_mode: "2".to_owned(),
+            binary_nature: BinaryNature::Factory,
+            client_product: "Smart Switch".to_owned(),
+            extra: Vec::new(),
+        }
+    }
+
+    /// The request of the older Kies client.
+    pub fn kies() -> Self {
+        Self {
+            access_mode: "2".to_owned(),
+            binary_nature: BinaryNature::Factory,
+            client_product: "Kies".to_owned(),
+            extra: vec![("DEVICE_CONTENTS_DATA_VERSION".to_owned(), String::new())],
+        }
+    }
+
+    pub fn binary_nature(mut self, binary_nature: BinaryNature) -> Self {
+        self.binary_nature = binary_nature;
+        self
+    }
+
+    /// Adds a field or replaces the value of a field added before.
+    pub fn field(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        let (name, value) = (name.into(), value.into());
+        match self.extra.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, v)) => *v = value,
+            None => self.extra.push((name, value)),
+        }
+        self
+    }
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self::smart_switch()
+    }
+}
+
+impl FromStr for Profile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "smart-switch" => Ok(Self::smart_switch()),
+            "kies" => Ok(Self::kies()),
+            _ => Err(format!(
+                "unknown profile `{s}`, expected smart-switch or kies"
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!("home".parse(), Ok(BinaryNature::Home));
+        assert_eq!("1".parse(), Ok(BinaryNature::Factory));
+        assert!("2".parse::<BinaryNature>().is_err());
+
+        let profile: Profile = "kies".parse().unwrap();
+        assert_eq!(profile.client_product, "Kies");
+        assert!("samfirm".parse::<Profile>().is_err());
+    }
+
+    #[test]
+    fn field() {
+        let profile = Profile::smart_switch()
+            .field("DEVICE_CONTENTS_DATA_VERSION", "1")
+            .field("DEVICE_CONTENTS_DATA_VERSION", "2");
+        assert_eq!(
+            profile.extra,
+            [("DEVICE_CONTENTS_DATA_VERSION".to_owned(), "2".to_owned())]
+        );
+    }
+}
@@ -1,44 +1,110 @@
-use std::io::Error;
+//! Progress reporting of downloads, decryptions and extractions.
+
+use std::fmt;
+use std::io;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 
-use indicatif::{ProgressBar, ProgressStyle};
+use serde::Serialize;
 use tokio::io::{AsyncRead, ReadBuf};
 
-pub fn new(size: u64) -> ProgressBar {
-    let style = ProgressStyle::default_bar()
-        .template("{spinner:.green} {msg} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {bytes_per_sec} {bytes}/{total_bytes} ({eta})")
-        .unwrap()
-        .progress_chars("#>-");
-    let pb = ProgressBar::new(size);
-    pb.set_style(style);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Phase {
+    Download,
+    Decrypt,
+    Extract,
+}
 
-    pb
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Download => "download",
+            Self::Decrypt => "decrypt",
+            Self::Extract => "extract",
+        })
+    }
 }
 
-pub fn wrap_reader<T>(inner: T, pb: ProgressBar) -> AsyncProgress<T>
+/// Receives the progress of an operation in bytes.
+///
+/// Closures taking the number of processed bytes implement it as well.
+pub trait Progress: Send + Sync {
+    /// Called when the operation starts with the total number of bytes.
+    fn start(&self, phase: Phase, total: u64) {
+        let _ = (phase, total);
+    }
+
+    fn inc(&self, n: u64);
+
+    fn finish(&self, phase: Phase) {
+        let _ = phase;
+    }
+}
+
+impl<F> Progress for F
+where
+    F: Fn(u64) + Send + Sync,
+{
+    fn inc(&self, n: u64) {
+        self(n);
+    }
+}
+
+/// Reports the bytes read from `inner` to `progress`.
+pub fn wrap_reader<T, P>(inner: T, progress: P) -> AsyncProgress<T, P>
 where
     T: AsyncRead + Unpin,
+    P: Progress,
 {
-    AsyncProgress { inner, pb }
+    AsyncProgress { inner, progress }
 }
 
-pub struct AsyncProgress<T> {
+pub struct AsyncProgress<T, P> {
     inner: T,
-    pb: ProgressBar,
+    progress: P,
 }
 
-impl<T> AsyncRead for AsyncProgress<T>
+impl<T, P> AsyncRead for AsyncProgress<T, P>
 where
     T: AsyncRead + Unpin,
+    P: Progress + Unpin,
 {
     fn poll_read(
         mut self: Pin<&mut Self>,
         cx: &mut Context<'_>,
         buf: &mut ReadBuf<'_>,
-    ) -> Poll<Result<(), Error>> {
+    ) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
         let result = Pin::new(&mut self.inner).poll_read(cx, buf);
-        self.pb.inc(buf.filled().len() as u64);
+        let n = buf.filled().len() - filled;
+        if n > 0 {
+            self.progress.inc(n as u64);
+        }
         result
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use tokio::io::AsyncReadExt;
+
+    #[tokio::test]
+    async fn count_read_bytes() {
+        let count = AtomicU64::new(0);
+        let data = [0u8; 100];
+        let mut reader = wrap_reader(&data[..], |n| {
+            count.fetch_add(n, Ordering::Relaxed);
+        });
+
+        let mut buf = [0; 30];
+        reader.read_exact(&mut buf).await.unwrap();
+        assert_eq!(count.load(Ordering::Relaxed), 30);
+        reader.read_to_end(&mut Vec::new()).await.unwrap();
+        assert_eq!(count.load(Ordering::Relaxed), 100);
+    }
+}
@@ -0,0 +1,253 @@
+//! Recording and replaying of the requests to the FOTA and FUS servers.
+//!
+//! Every exchange is stored as `{seq:03}-{name}.json` in the directory so that
+//! a replay serves the responses in the order they were recorded. Requests that
+//! fail without a response aren't recorded and don't use up a number.
+//!
+//! The `Authorization`, `Cookie` and `Set-Cookie` headers are redacted. The
+//! encrypted nonce of the FUS responses is kept because a replay needs it.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::{anyhow, Context};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, COOKIE, SET_COOKIE};
+use reqwest::{Request, Response};
+use serde::{Deserialize, Serialize};
+
+use crate::Error;
+
+#[derive(Debug, Clone)]
+pub enum Mode {
+    Record(PathBuf),
+    Replay(PathBuf),
+}
+
+/// A recorded request and its response.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Exchange {
+    pub method: String,
+    pub url: String,
+    pub request_headers: Vec<(String, String)>,
+    pub request_body: Option<String>,
+    pub status: u16,
+    pub response_headers: Vec<(String, String)>,
+    pub response_body: String,
+}
+
+pub struct Recorder {
+    mode: Mode,
+    seq: AtomicUsize,
+}
+
+impl Recorder {
+    pub fn new(mode: Mode) -> Result<Self, Error> {
+        if let Mode::Record(dir) = &mode {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create directory {}", dir.display()))?;
+        }
+        Ok(Self {
+            mode,
+            seq: AtomicUsize::new(1),
+        })
+    }
+
+    pub async fn send(
+        &self,
+        client: &reqwest::Client,
+        name: &str,
+        req: Request,
+    ) -> Result<Response, Error> {
+        match &self.mode {
+            Mode::Record(dir) => {
+                let method = req.method().to_string();
+                let url = req.url().to_string();
+                let request_headers = headers_to_vec(req.headers());
+                let request_body = req
+                    .body()
+                    .and_then(|body| body.as_bytes())
+                    .map(|body| String::from_utf8_lossy(body).into_owned());
+
+                let resp = client.execute(req).await?;
+                let status = resp.status();
+                let headers = resp.headers().clone();
+                let body = resp.bytes().await?;
+
+                let exchange = Exchange {
+                    method,
+                    url,
+                    request_headers,
+                    request_body,
+                    status: status.as_u16(),
+                    response_headers: headers_to_vec(&headers),
+                    response_body: String::from_utf8_lossy(&body).into_owned(),
+                };
+                let seq = self.seq.fetch_add(1, Ordering::Relaxed);
+                let path = exchange_path(dir, seq, name);
+                fs::write(&path, serde_json::to_vec_pretty(&exchange)?)
+                    .with_context(|| format!("failed to record {}", path.display()))?;
+
+                let mut resp = http::Response::new(body);
+                *resp.status_mut() = status;
+                *resp.headers_mut() = headers;
+                Ok(resp.into())
+            }
+            Mode::Replay(dir) => {
+                let seq = self.seq.fetch_add(1, Ordering::Relaxed);
+                let path = exchange_path(dir, seq, name);
+                let content = fs::read(&path)
+                    .with_context(|| format!("no recorded response {}", path.display()))?;
+                let exchange: Exchange = serde_json::from_slice(&content)
+                    .with_context(|| format!("invalid recording {}", path.display()))?;
+
+                if exchange.method != req.method().as_str() || exchange.url != req.url().as_str() {
+                    tracing::warn!(
+                        "replaying {} {} for request {} {}",
+                        exchange.method,
+                        exchange.url,
+                        req.method(),
+                        req.url()
+                    );
+                }
+
+                let mut resp = http::Response::new(exchange.response_body);
+                *resp.status_mut() = exchange.status.try_into()?;
+                for (name, value) in exchange.response_headers {
+                    resp.headers_mut()
+                        .append(HeaderName::try_from(name)?, HeaderValue::try_from(value)?);
+                }
+                Ok(resp.into())
+            }
+        }
+    }
+
+    /// Downloads of firmware files are never recorded and can't be replayed.
+    pub fn check_unrecorded(&self, url: &str) -> Result<(), Error> {
+        match &self.mode {
+            Mode::Record(_) => Ok(()),
+            Mode::Replay(_) => Err(anyhow!("{url} can't be replayed")),
+        }
+    }
+}
+
+fn exchange_path(dir: &Path, seq: usize, name: &str) -> PathBuf {
+    dir.join(format!("{seq:03}-{name}.json"))
+}
+
+/// Headers with credentials that aren't written to the recordings.
+const REDACTED: [HeaderName; 3] = [AUTHORIZATION, COOKIE, SET_COOKIE];
+
+fn headers_to_vec(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if REDACTED.contains(name) {
+                "<redacted>".to_owned()
+            } else {
+                String::from_utf8_lossy(value.as_bytes()).into_owned()
+            };
+            (name.to_string(), value)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    use aes::cipher::block_padding::Pkcs7;
+    use aes::cipher::{BlockEncryptMut, KeyIvInit};
+    use base64ct::{Base64, Encoding};
+
+    use crate::binary_info::DecryptKey;
+    use crate::client::Client;
+    use crate::fus::tests::BINARY_INFORM_XML;
+
+    const VERSION_XML: &str = "<versioninfo><firmware><version><latest>\
+        G991BXXU5CVDD/G991BOXM5CVDD/G991BXXU5CVDD</latest></version></firmware></versioninfo>";
+
+    fn encrypted_nonce() -> String {
+        type Aes256CbcEnc = cbc::Encryptor<aes::Aes256>;
+        let key = b"vicopx7dqu06emacgpnpy8j8zwhduwlh";
+        let cipher = Aes256CbcEnc::new_from_slices(key, &key[..16]).unwrap();
+        let nonce = cipher.encrypt_padded_vec_mut::<Pkcs7>(b"0123456789abcdef");
+        Base64::encode_string(&nonce)
+    }
+
+    pub(crate) fn write(dir: &Path, seq: usize, name: &str, headers: &[(&str, &str)], body: &str) {
+        let exchange = Exchange {
+            method: String::new(),
+            url: String::new(),
+            request_headers: vec![],
+            request_body: None,
+            status: 200,
+            response_headers: headers
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            response_body: body.to_owned(),
+        };
+        let content = serde_json::to_vec(&exchange).unwrap();
+        fs::write(exchange_path(dir, seq, name), content).unwrap();
+    }
+
+    #[test]
+    fn redacted_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, "FUS nonce=\"abc\"".parse().unwrap());
+        headers.insert(SET_COOKIE, "JSESSIONID=123".parse().unwrap());
+        headers.insert("nonce", "abc".parse().unwrap());
+        let mut headers = headers_to_vec(&headers);
+        headers.sort();
+        assert_eq!(
+            headers,
+            [
+                ("authorization".to_owned(), "<redacted>".to_owned()),
+                ("nonce".to_owned(), "abc".to_owned()),
+                ("set-cookie".to_owned(), "<redacted>".to_owned()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn replay_file_info() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let nonce = encrypted_nonce();
+        write(dir.path(), 1, "fetch_version", &[], VERSION_XML);
+        write(dir.path(), 2, "generate_nonce", &[("nonce", &nonce)], "");
+        write(
+            dir.path(),
+            3,
+            "NF_DownloadBinaryInform.do",
+            &[],
+            BINARY_INFORM_XML,
+        );
+
+        let client = Client::builder()
+            .record(Mode::Replay(dir.path().to_owned()))
+            .build()
+            .unwrap();
+
+        let version = client.fetch_version("SM-G991B", "EUX").await.unwrap();
+        assert_eq!(
+            version,
+            "G991BXXU5CVDD/G991BOXM5CVDD/G991BXXU5CVDD/G991BXXU5CVDD"
+        );
+
+        let mut nonce = client.generate_nonce().await.unwrap();
+        assert_eq!(nonce.value, "0123456789abcdef");
+
+        let info = client
+            .file_info("SM-G991B", "0", "EUX", &version, &mut nonce)
+            .await
+            .unwrap();
+        assert_eq!(info.display_name, "Galaxy S21 5G");
+        assert_eq!(info.binary_size, 1234);
+        assert!(matches!(info.decrypt_key, DecryptKey::V4(_)));
+
+        let err = client.fetch_version("SM-G991B", "EUX").await.unwrap_err();
+        assert!(err.to_string().starts_with("no recorded response"));
+    }
+}
@@ -0,0 +1,236 @@
+//! Bundled table of region (CSC) codes.
+//!
+//! Firmwares of the same multi-CSC group are shared by all of its regions, e.g.
+//! the `OXM` firmware of a `G991B` is the same for `DBT` and `XEF`.
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Region {
+    pub code: &'static str,
+    pub country: &'static str,
+    /// ISO 3166-1 alpha-2 codes of the countries.
+    pub country_codes: &'static [&'static str],
+    pub carrier: Option<&'static str>,
+    /// The multi-CSC group of the region.
+    pub group: Option<&'static str>,
+}
+
+const fn region(
+    code: &'static str,
+    country: &'static str,
+    country_codes: &'static [&'static str],
+    carrier: Option<&'static str>,
+    group: Option<&'static str>,
+) -> Region {
+    Region {
+        code,
+        country,
+        country_codes,
+        carrier,
+        group,
+    }
+}
+
+const OXM: Option<&str> = Some("OXM");
+const OYN: Option<&str> = Some("OYN");
+const OYV: Option<&str> = Some("OYV");
+const ODM: Option<&str> = Some("ODM");
+const SER: Option<&str> = Some("SER");
+
+#[rustfmt::skip]
+pub static REGIONS: &[Region] = &[
+    // Europe
+    region("EUX", "Europe", &[], None, OXM),
+    region("ATO", "Austria", &["AT"], None, OXM),
+    region("BGL", "Bulgaria", &["BG"], None, OXM),
+    region("DBT", "Germany", &["DE"], None, OXM),
+    region("DTM", "Germany", &["DE"], Some("Telekom"), OXM),
+    region("VD2", "Germany", &["DE"], Some("Vodafone"), OXM),
+    region("VIA", "Germany", &["DE"], Some("O2"), OXM),
+    region("BTU", "United Kingdom", &["GB"], None, OXM),
+    region("EVR", "United Kingdom", &["GB"], Some("EE"), OXM),
+    region("H3G", "United Kingdom", &["GB"], Some("Three"), OXM),
+    region("O2U", "United Kingdom", &["GB"], Some("O2"), OXM),
+    region("VOD", "United Kingdom", &["GB"], Some("Vodafone"), OXM),
+    region("XEF", "France", &["FR"], None, OXM),
+    region("BOG", "France", &["FR"], Some("Bouygues Telecom"), OXM),
+    region("FTM", "France", &["FR"], Some("Orange"), OXM),
+    region("SFR", "France", &["FR"], Some("SFR"), OXM),
+    region("ITV", "Italy", &["IT"], None, OXM),
+    region("OMN", "Italy", &["IT"], Some("Vodafone"), OXM),
+    region("TIM", "Italy", &["IT"], Some("TIM"), OXM),
+    region("PHE", "Spain", &["ES"], None, OXM),
+    region("TPH", "Portugal", &["PT"], None, OXM),
+    region("XEN", "Netherlands", &["NL"], None, OXM),
+    region("LUX", "Luxembourg", &["LU"], None, OXM),
+    region("AUT", "Switzerland", &["CH"], None, OXM),
+    region("NEE", "Nordic countries", &["DK", "FI", "NO", "SE"], None, OXM),
+    region("XEO", "Poland", &["PL"], None, OXM),
+    region("PLS", "Poland", &["PL"], Some("Plus"), OXM),
+    region("XEZ", "Czech Republic", &["CZ"], None, OXM),
+    region("ORX", "Slovakia", &["SK"], None, OXM),
+    region("XEH", "Hungary", &["HU"], None, OXM),
+    region("ROM", "Romania", &["RO"], None, OXM),
+    region("SEE", "South East Europe", &["AL", "BA", "HR", "ME", "MK", "RS", "SI"], None, OXM),
+    region("SER", "Russia", &["RU"], None, SER),
+    region("SEK", "Ukraine", &["UA"], None, SER),
+    region("CAU", "Caucasus", &["AM", "AZ", "GE"], None, SER),
+    region("TUR", "Turkey", &["TR"], None, None),
+    // North America
+    region("XAA", "United States", &["US"], None, OYN),
+    region("ATT", "United States", &["US"], Some("AT&T"), OYN),
+    region("SPR", "United States", &["US"], Some("Sprint"), OYN),
+    region("TMB", "United States", &["US"], Some("T-Mobile"), OYN),
+    region("USC", "United States", &["US"], Some("US Cellular"), OYN),
+    region("VZW", "United States", &["US"], Some("Verizon"), OYN),
+    region("XAC", "Canada", &["CA"], None, OYV),
+    region("BMC", "Canada", &["CA"], Some("Bell"), OYV),
+    region("RWC", "Canada", &["CA"], Some("Rogers"), OYV),
+    region("TLS", "Canada", &["CA"], Some("Telus"), OYV),
+    // Latin America
+    region("MXO", "Mexico", &["MX"], None, None),
+    region("TCE", "Mexico", &["MX"], Some("Telcel"), None),
+    region("ZTO", "Brazil", &["BR"], None, None),
+    region("ZTA", "Brazil", &["BR"], Some("Claro"), None),
+    region("ZVV", "Brazil", &["BR"], Some("Vivo"), None),
+    region("ARO", "Argentina", &["AR"], None, None),
+    region("CHO", "Chile", &["CL"], None, None),
+    region("COO", "Colombia", &["CO"], None, None),
+    region("TPA", "Panama", &["PA"], None, None),
+    // Asia and Oceania
+    region("INS", "India", &["IN"], None, ODM),
+    region("INU", "India", &["IN"], None, ODM),
+    region("PAK", "Pakistan", &["PK"], None, None),
+    region("XSA", "Australia", &["AU"], None, None),
+    region("OPS", "Australia", &["AU"], Some("Optus"), None),
+    region("TEL", "Australia", &["AU"], Some("Telstra"), None),
+    region("VAU", "Australia", &["AU"], Some("Vodafone"), None),
+    region("XNZ", "New Zealand", &["NZ"], None, None),
+    region("XSP", "Singapore", &["SG"], None, None),
+    region("XME", "Malaysia", &["MY"], None, None),
+    region("XID", "Indonesia", &["ID"], None, None),
+    region("XTC", "Philippines", &["PH"], None, None),
+    region("THL", "Thailand", &["TH"], None, None),
+    region("XXV", "Vietnam", &["VN"], None, None),
+    region("CHC", "China", &["CN"], None, None),
+    region("TGY", "Hong Kong", &["HK"], None, None),
+    region("BRI", "Taiwan", &["TW"], None, None),
+    region("KOO", "South Korea", &["KR"], None, None),
+    region("KTC", "South Korea", &["KR"], Some("KT"), None),
+    region("LUC", "South Korea", &["KR"], Some("LG U+"), None),
+    region("SKC", "South Korea", &["KR"], Some("SK Telecom"), None),
+    region("DCM", "Japan", &["JP"], Some("NTT Docomo"), None),
+    region("KDI", "Japan", &["JP"], Some("au (KDDI)"), None),
+    // Middle East and Africa
+    region("ILO", "Israel", &["IL"], None, None),
+    region("XSG", "United Arab Emirates", &["AE"], None, None),
+    region("KSA", "Saudi Arabia", &["SA"], None, None),
+    region("EGY", "Egypt", &["EG"], None, None),
+    region("XFA", "South Africa", &["ZA"], None, None),
+];
+
+pub fn find(code: &str) -> Option<&'static Region> {
+    REGIONS.iter().find(|r| r.code.eq_ignore_ascii_case(code))
+}
+
+/// Returns the regions of a country by its two-letter ISO code or its name.
+///
+/// Names match whole words, e.g. `zealand` or `new zealand` but not `zeal`.
+pub fn by_country(country: &str) -> Vec<&'static Region> {
+    let is_code = country.len() == 2 && country.chars().all(|c| c.is_ascii_alphabetic());
+    REGIONS
+        .iter()
+        .filter(|r| {
+            if is_code {
+                r.country_codes
+                    .iter()
+                    .any(|c| c.eq_ignore_ascii_case(country))
+            } else {
+                matches_words(r.country, country)
+            }
+        })
+        .collect()
+}
+
+/// Searches the code, country, carrier and group of the regions.
+pub fn search(query: &str) -> Vec<&'static Region> {
+    REGIONS
+        .iter()
+        .filter(|r| {
+            r.code.eq_ignore_ascii_case(query)
+                || r.group.is_some_and(|g| g.eq_ignore_ascii_case(query))
+                || contains_ignore_case(r.country, query)
+                || r.carrier.is_some_and(|c| contains_ignore_case(c, query))
+        })
+        .collect()
+}
+
+/// Returns known codes that are similar to an unknown `code`, best match first.
+pub fn suggest(code: &str) -> Vec<&'static str> {
+    let code = code.to_ascii_uppercase();
+    let mut matches = REGIONS
+        .iter()
+        .map(|r| (strsim::osa_distance(&code, r.code), r.code))
+        .filter(|(distance, _)| *distance <= 1)
+        .collect::<Vec<_>>();
+    matches.sort();
+    matches.into_iter().map(|(_, code)| code).collect()
+}
+
+/// Checks if the words of `query` appear one after the other in `name`.
+fn matches_words(name: &str, query: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    let query = query.to_ascii_lowercase();
+    let (name, query) = (
+        name.split_whitespace().collect::<Vec<_>>(),
+        query.split_whitespace().collect::<Vec<_>>(),
+    );
+    !query.is_empty() && name.windows(query.len()).any(|words| words == query)
+}
+
+fn contains_ignore_case(haystack: &str, needle: &str) -> bool {
+    haystack
+        .to_ascii_lowercase()
+        .contains(&needle.to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unique_codes() {
+        for (i, region) in REGIONS.iter().enumerate() {
+            assert!(
+                REGIONS[i + 1..].iter().all(|r| r.code != region.code),
+                "{}",
+                region.code
+            );
+        }
+    }
+
+    #[test]
+    fn lookup() {
+        assert_eq!(find("dbt").map(|r| r.country), Some("Germany"));
+        assert!(find("EXU").is_none());
+
+        let codes = |regions: Vec<&Region>| regions.iter().map(|r| r.code).collect::<Vec<_>>();
+        assert_eq!(codes(by_country("DE")), ["DBT", "DTM", "VD2", "VIA"]);
+        assert_eq!(codes(by_country("FI")), ["NEE"]);
+        assert_eq!(codes(by_country("zealand")), ["XNZ"]);
+        assert_eq!(codes(by_country("New Zealand")), ["XNZ"]);
+        assert!(by_country("zeal").is_empty());
+        assert_eq!(codes(by_country("IN")), ["INS", "INU"]);
+        assert_eq!(codes(by_country("in")), ["INS", "INU"]);
+        assert_eq!(codes(search("oyv")), ["XAC", "BMC", "RWC", "TLS"]);
+        assert_eq!(codes(search("telstra")), ["TEL"]);
+    }
+
+    #[test]
+    fn suggestions() {
+        assert_eq!(suggest("EXU"), ["EUX"]);
+        assert_eq!(suggest("XEF")[0], "XEF");
+        assert!(suggest("QQQ").is_empty());
+    }
+}
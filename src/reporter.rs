@@ -0,0 +1,349 @@
+//! Progress bars, or JSON progress events with `--progress json`.
+//!
+//! Jobs that run at the same time share a [`Multi`] with one line per job. The
+//! events are written to the [`Events`] sink that is passed to the jobs.
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use serde::Serialize;
+
+use frigg::progress::{Phase, Progress};
+use frigg::Error;
+
+/// Minimum time between two `progress` events.
+const EVENT_INTERVAL: Duration = Duration::from_millis(250);
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// The output of the newline-delimited JSON events, shared by all jobs.
+pub type Events = Arc<Mutex<Box<dyn Write + Send>>>;
+
+/// Hides the progress bars that are created afterwards.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Writes the progress of the jobs that are given the sink as events to `out`.
+pub fn events(out: Box<dyn Write + Send>) -> Events {
+    Arc::new(Mutex::new(out))
+}
+
+/// Opens the file descriptor of `--progress-fd` for the events.
+///
+/// The descriptor is duplicated, so the file doesn't close it when it's dropped. The
+/// standard streams are rejected, stderr is already the default for the events.
+#[cfg(unix)]
+pub fn open_fd(fd: i32) -> Result<File, Error> {
+    use std::os::fd::BorrowedFd;
+
+    use anyhow::Context;
+
+    if fd <= 2 {
+        return Err(anyhow::anyhow!(
+            "invalid file descriptor {fd}, it must not be stdin, stdout or stderr"
+        ));
+    }
+    // SAFETY: the borrow only lives for the duplication below and the descriptor
+    // isn't closed in the meantime. An fd that isn't open makes the duplication
+    // fail with EBADF instead of taking over a descriptor of another file.
+    #[allow(unsafe_code)]
+    let borrowed = unsafe { BorrowedFd::borrow_raw(fd) };
+    let fd = borrowed
+        .try_clone_to_owned()
+        .with_context(|| format!("invalid file descriptor {fd}"))?;
+    Ok(File::from(fd))
+}
+
+#[cfg(not(unix))]
+pub fn open_fd(_fd: i32) -> Result<File, Error> {
+    Err(anyhow::anyhow!("--progress-fd is only supported on Unix"))
+}
+
+fn draw_target() -> ProgressDrawTarget {
+    if ENABLED.load(Ordering::Relaxed) {
+        ProgressDrawTarget::stderr()
+    } else {
+        ProgressDrawTarget::hidden()
+    }
+}
+
+fn bytes_style() -> ProgressStyle {
+    ProgressStyle::default_bar()
+        .template("{spinner:.green} {prefix:.bold} {msg} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {bytes_per_sec} {bytes}/{total_bytes} ({eta})")
+        .unwrap()
+        .progress_chars("#>-")
+}
+
+/// The progress bars of jobs that run at the same time, one line per job.
+///
+/// Once there's more than one job, a last line shows the total of all jobs.
+#[derive(Clone)]
+pub struct Multi {
+    mp: MultiProgress,
+    total: ProgressBar,
+    jobs: Arc<Mutex<usize>>,
+    events: Option<Events>,
+}
+
+impl Multi {
+    pub fn new(events: Option<Events>) -> Self {
+        let total = ProgressBar::new(0);
+        total.set_style(bytes_style());
+        total.set_prefix("total");
+
+        Self {
+            mp: MultiProgress::with_draw_target(draw_target()),
+            total,
+            jobs: Arc::new(Mutex::new(0)),
+            events,
+        }
+    }
+
+    /// Adds the line of a job named `name` that starts with `phase`.
+    pub fn add(&self, name: &str, phase: Phase, size: u64) -> Bar {
+        let pb = ProgressBar::new(size);
+        pb.set_style(bytes_style());
+
+        let mut jobs = self.jobs.lock().unwrap();
+        let pb = match *jobs {
+            0 => self.mp.add(pb),
+            1 => {
+                self.mp.add(self.total.clone());
+                self.mp.insert_before(&self.total, pb)
+            }
+            _ => self.mp.insert_before(&self.total, pb),
+        };
+        *jobs += 1;
+
+        let bar = Bar {
+            pb,
+            total: self.total.clone(),
+            events: self.events.clone(),
+            state: Arc::new(Mutex::new(State {
+                name: (!name.is_empty()).then(|| name.to_owned()),
+                phase,
+                last_event: None,
+            })),
+        };
+        bar.set_phase(phase);
+        bar
+    }
+
+    /// Removes the total line once all jobs are done.
+    pub fn clear(&self) {
+        self.total.finish_and_clear();
+    }
+}
+
+/// A single job without a name.
+pub fn new(events: Option<Events>, phase: Phase, size: u64) -> Bar {
+    Multi::new(events).add("", phase, size)
+}
+
+/// A progress bar that counts items instead of bytes.
+pub fn counter(len: u64) -> ProgressBar {
+    let style = ProgressStyle::default_bar()
+        .template("{spinner:.green} {msg} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {pos}/{len}")
+        .unwrap()
+        .progress_chars("#>-");
+    let pb = ProgressBar::with_draw_target(Some(len), draw_target());
+    pb.set_style(style);
+
+    pb
+}
+
+/// The line of a job that also emits the events of `--progress json`.
+#[derive(Clone)]
+pub struct Bar {
+    pb: ProgressBar,
+    /// The total line of all jobs.
+    total: ProgressBar,
+    events: Option<Events>,
+    state: Arc<Mutex<State>>,
+}
+
+struct State {
+    name: Option<String>,
+    phase: Phase,
+    last_event: Option<Instant>,
+}
+
+#[derive(Serialize)]
+struct Event<'a> {
+    event: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    job: Option<&'a str>,
+    phase: Phase,
+    done: u64,
+    total: Option<u64>,
+    /// Bytes per second.
+    rate: f64,
+    /// Estimated seconds until the phase is done.
+    eta: Option<f64>,
+}
+
+impl Bar {
+    pub fn inc(&self, n: u64) {
+        self.pb.inc(n);
+        self.total.inc(n);
+        self.emit("progress", false);
+    }
+
+    /// Moves to `pos`, e.g. when a download continues. The skipped bytes count as done.
+    pub fn set_position(&self, pos: u64) {
+        self.total.inc(pos.saturating_sub(self.pb.position()));
+        self.pb.set_position(pos);
+    }
+
+    fn set_phase(&self, phase: Phase) {
+        let mut state = self.state.lock().unwrap();
+        state.phase = phase;
+        match &state.name {
+            Some(name) => self.pb.set_prefix(format!("{name} {phase}")),
+            None => self.pb.set_prefix(phase.to_string()),
+        }
+    }
+
+    fn emit(&self, event: &'static str, force: bool) {
+        let Some(events) = &self.events else {
+            return;
+        };
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        if !force && state.last_event.is_some_and(|t| now - t < EVENT_INTERVAL) {
+            return;
+        }
+        state.last_event = Some(now);
+
+        let total = self.pb.length();
+        let done = self.pb.position();
+        let rate = self.pb.per_sec();
+        let eta = match total {
+            Some(total) if rate > 0.0 => Some(total.saturating_sub(done) as f64 / rate),
+            _ => None,
+        };
+        let event = Event {
+            event,
+            job: state.name.as_deref(),
+            phase: state.phase,
+            done,
+            total,
+            rate,
+            eta,
+        };
+
+        let mut out = events.lock().unwrap();
+        // The progress is informational, a closed pipe must not abort the work.
+        let _ = serde_json::to_writer(&mut *out, &event)
+            .map_err(io::Error::from)
+            .and_then(|()| writeln!(out))
+            .and_then(|()| out.flush());
+    }
+}
+
+impl Progress for Bar {
+    fn start(&self, phase: Phase, total: u64) {
+        self.set_phase(phase);
+        self.pb.set_length(total);
+        self.pb.reset();
+        self.total.inc_length(total);
+        self.emit("start", true);
+    }
+
+    fn inc(&self, n: u64) {
+        Bar::inc(self, n);
+    }
+
+    fn finish(&self, phase: Phase) {
+        self.set_phase(phase);
+        self.emit("finish", true);
+    }
+}
+
+impl Deref for Bar {
+    type Target = ProgressBar;
+
+    fn deref(&self) -> &ProgressBar {
+        &self.pb
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn json_events() {
+        let out = Shared::default();
+        let bar = new(Some(events(Box::new(out.clone()))), Phase::Download, 0);
+        bar.start(Phase::Decrypt, 100);
+        bar.inc(40);
+        // Too soon after the last event.
+        bar.inc(10);
+        bar.finish(Phase::Decrypt);
+
+        let out = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+        let events = out
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["event"], "start");
+        assert_eq!(events[0]["phase"], "decrypt");
+        assert_eq!(events[0]["total"], 100);
+        assert_eq!(events[1]["event"], "finish");
+        assert_eq!(events[1]["done"], 50);
+    }
+
+    #[test]
+    fn multi_total() {
+        let multi = Multi::new(None);
+        let first = multi.add("SM-S911B/EUX", Phase::Download, 0);
+        let second = multi.add("SM-S918B/EUX", Phase::Download, 0);
+        first.start(Phase::Download, 100);
+        first.set_position(30);
+        second.start(Phase::Extract, 50);
+        second.inc(20);
+
+        assert_eq!(first.prefix(), "SM-S911B/EUX download");
+        assert_eq!(second.prefix(), "SM-S918B/EUX extract");
+        assert_eq!(multi.total.length(), Some(150));
+        assert_eq!(multi.total.position(), 50);
+        assert_eq!(new(None, Phase::Decrypt, 10).prefix(), "decrypt");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn open_fd_duplicates() {
+        use std::os::fd::AsRawFd;
+
+        assert!(open_fd(1).is_err());
+        assert!(open_fd(i32::MAX).is_err());
+
+        let file = tempfile::tempfile().unwrap();
+        let mut events = open_fd(file.as_raw_fd()).unwrap();
+        writeln!(events, "{{}}").unwrap();
+        drop(events);
+        // The original descriptor stays open.
+        assert_eq!(file.metadata().unwrap().len(), 3);
+    }
+}
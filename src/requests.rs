@@ -1,80 +1,60 @@
-use xml::writer::{EmitterConfig, Error as XmlError, EventWriter, XmlEvent};
-
-type XmlWriter<'a> = EventWriter<&'a mut Vec<u8>>;
-
-fn fus_xml<F>(body: F) -> Result<String, crate::Error>
-where
-    F: Fn(&mut XmlWriter) -> Result<(), XmlError>,
-{
-    let mut buf = Vec::new();
-    let mut w = EmitterConfig::new().create_writer(&mut buf);
-    w.write(XmlEvent::start_element("FUSMsg"))?;
-    w.write(XmlEvent::start_element("FUSHdr"))?;
-    w.write(XmlEvent::start_element("ProtoVer"))?;
-    w.write("1.0")?;
-    w.write(XmlEvent::end_element())?; // ProtoVer
-    w.write(XmlEvent::end_element())?; // FUSHdr
-
-    w.write(XmlEvent::start_element("FUSBody"))?;
-    w.write(XmlEvent::start_element("Put"))?;
-
-    body(&mut w)?;
-
-    w.write(XmlEvent::end_element())?; // Put
-    w.write(XmlEvent::end_element())?; // FUSBody
-    w.write(XmlEvent::end_element())?; // FUSMsg
-
-    String::from_utf8(buf).map_err(Into::into)
-}
-
-fn fus_attr(w: &mut XmlWriter<'_>, name: &str, data: &str) -> Result<(), XmlError> {
-    w.write(XmlEvent::start_element(name))?;
-    w.write(XmlEvent::start_element("Data"))?;
-    w.write(data)?;
-    w.write(XmlEvent::end_element())?; // Data
-    w.write(XmlEvent::end_element()) // $name
-}
+use crate::fus::{Fields, FusMsg};
+use crate::profile::Profile;
+use crate::Error;
+
+pub fn file_info(
+    model: &str,
+    imei: &str,
+    region: &str,
+    version: &str,
+    check: &str,
+    profile: &Profile,
+) -> Result<String, Error> {
+    let mut fields: Fields = [
+        ("ACCESS_MODE", profile.access_mode.as_str()),
+        ("BINARY_NATURE", profile.binary_nature.value()),
+        ("CLIENT_PRODUCT", &profile.client_product),
+        ("DEVICE_IMEI_PUSH", imei),
+        ("DEVICE_FW_VERSION", version),
+        ("DEVICE_LOCAL_CODE", region),
+        ("DEVICE_MODEL_NAME", model),
+    ]
+    .into_iter()
+    .collect();
+    // Extra fields can also replace the ones of the profile.
+    for (name, value) in &profile.extra {
+        fields.insert(name, value);
+    }
+    fields.insert("LOGIC_CHECK", check);
 
-pub fn file_info(model: &str, imei: &str, region: &str, version: &str, check: &str) -> String {
-    fus_xml(|w| {
-        fus_attr(w, "ACCESS_MODE", "2")?;
-        fus_attr(w, "BINARY_NATURE", "1")?;
-        fus_attr(w, "CLIENT_PRODUCT", "Smart Switch")?;
-        fus_attr(w, "DEVICE_IMEI_PUSH", imei)?;
-        fus_attr(w, "DEVICE_FW_VERSION", version)?;
-        fus_attr(w, "DEVICE_LOCAL_CODE", region)?;
-        fus_attr(w, "DEVICE_MODEL_NAME", model)?;
-        fus_attr(w, "LOGIC_CHECK", check)
-    })
-    .unwrap()
+    FusMsg::new(fields).to_xml()
 }
 
-pub fn init_download(file: &str, check: &str) -> String {
-    fus_xml(|w| {
-        fus_attr(w, "BINARY_FILE_NAME", file)?;
-        fus_attr(w, "LOGIC_CHECK", check)
-    })
-    .unwrap()
+pub fn init_download(file: &str, check: &str) -> Result<String, Error> {
+    let fields = [("BINARY_FILE_NAME", file), ("LOGIC_CHECK", check)];
+    FusMsg::new(fields.into_iter().collect::<Fields>()).to_xml()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    use xml::writer::EmitterConfig;
+    use crate::profile::BinaryNature;
 
     #[test]
     fn empty_fus_xml() {
-        let xml = fus_xml(|_| Ok(())).unwrap();
+        let xml = FusMsg::new(Fields::new()).to_xml().unwrap();
         assert_eq!(
             xml,
-            r#"<?xml version="1.0" encoding="utf-8"?><FUSMsg><FUSHdr><ProtoVer>1.0</ProtoVer></FUSHdr><FUSBody><Put /></FUSBody></FUSMsg>"#
+            r#"<?xml version="1.0" encoding="utf-8"?><FUSMsg><FUSHdr><ProtoVer>1.0</ProtoVer></FUSHdr><FUSBody><Put/></FUSBody></FUSMsg>"#
         );
     }
 
     #[test]
     fn test_fus_xml() {
-        let xml = fus_xml(|w| fus_attr(w, "Foo", "Bar")).unwrap();
+        let xml = FusMsg::new([("Foo", "Bar")].into_iter().collect::<Fields>())
+            .to_xml()
+            .unwrap();
         assert_eq!(
             xml,
             r#"<?xml version="1.0" encoding="utf-8"?><FUSMsg><FUSHdr><ProtoVer>1.0</ProtoVer></FUSHdr><FUSBody><Put><Foo><Data>Bar</Data></Foo></Put></FUSBody></FUSMsg>"#
@@ -82,16 +62,41 @@ mod tests {
     }
 
     #[test]
-    fn test_fus_attr() {
-        let mut buf = Vec::new();
-        let mut w = EmitterConfig::new().create_writer(&mut buf);
+    fn invalid_field_name() {
+        let profile = Profile::default().field("A B", "1");
+        assert!(file_info("SM-G991B", "0", "EUX", "V", "C", &profile).is_err());
+    }
 
-        let _ = fus_attr(&mut w, "Foo", "Bar");
+    #[test]
+    fn file_info_profile() {
+        let profile = Profile::kies()
+            .binary_nature(BinaryNature::Home)
+            .field("DEVICE_CONTENTS_DATA_VERSION", "1");
+        let xml = file_info("SM-G991B", "0", "EUX", "V", "C", &profile).unwrap();
+        let msg = FusMsg::<Fields>::from_xml(&xml).unwrap();
+        let fields: Vec<_> = msg.body.put.iter().collect();
+        assert_eq!(
+            fields,
+            [
+                ("ACCESS_MODE", "2"),
+                ("BINARY_NATURE", "0"),
+                ("CLIENT_PRODUCT", "Kies"),
+                ("DEVICE_IMEI_PUSH", "0"),
+                ("DEVICE_FW_VERSION", "V"),
+                ("DEVICE_LOCAL_CODE", "EUX"),
+                ("DEVICE_MODEL_NAME", "SM-G991B"),
+                ("DEVICE_CONTENTS_DATA_VERSION", "1"),
+                ("LOGIC_CHECK", "C"),
+            ]
+        );
+    }
 
-        let xml = String::from_utf8(buf).unwrap();
+    #[test]
+    fn init_download_request() {
+        let xml = init_download("file.zip.enc4", "C").unwrap();
         assert_eq!(
             xml,
-            r#"<?xml version="1.0" encoding="utf-8"?><Foo><Data>Bar</Data></Foo>"#
+            r#"<?xml version="1.0" encoding="utf-8"?><FUSMsg><FUSHdr><ProtoVer>1.0</ProtoVer></FUSHdr><FUSBody><Put><BINARY_FILE_NAME><Data>file.zip.enc4</Data></BINARY_FILE_NAME><LOGIC_CHECK><Data>C</Data></LOGIC_CHECK></Put></FUSBody></FUSMsg>"#
         );
     }
 }
@@ -0,0 +1,145 @@
+//! Looking up the latest firmware of a model in many regions at once.
+
+use std::cmp::Reverse;
+
+use futures_util::stream::{self, StreamExt};
+use serde::Serialize;
+
+use crate::client::Client;
+use crate::Error;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Found {
+    pub region: String,
+    pub version: Option<String>,
+    pub os_version: Option<String>,
+    pub size: Option<u64>,
+    /// Why no firmware or file info was found.
+    pub error: Option<String>,
+}
+
+impl Found {
+    /// The last five characters of the PDA version (bootloader, major version,
+    /// year, month and build), which sort newer builds after older ones.
+    pub fn build(&self) -> Option<&str> {
+        let pda = self.version.as_deref()?.split('/').next()?;
+        pda.get(pda.len().checked_sub(5)?..)
+    }
+}
+
+/// Fetches the latest version of `model` in all `regions` with at most `concurrency`
+/// requests at a time.
+///
+/// With an `imei` the file info of every found version is fetched as well, also
+/// with at most `concurrency` requests at a time.
+pub async fn scan<F>(
+    client: &Client,
+    model: &str,
+    regions: &[&str],
+    concurrency: usize,
+    imei: Option<&str>,
+    mut on_done: F,
+) -> Result<Vec<Found>, Error>
+where
+    F: FnMut(&Found),
+{
+    let mut results = stream::iter(regions)
+        .map(|region| async move {
+            let version = client.fetch_version(model, region).await;
+            let (version, error) = match version {
+                Ok(version) => (Some(version), None),
+                Err(e) => (None, Some(e.to_string())),
+            };
+            Found {
+                region: region.to_string(),
+                version,
+                os_version: None,
+                size: None,
+                error,
+            }
+        })
+        .buffer_unordered(concurrency.max(1))
+        .inspect(|found| {
+            if imei.is_none() || found.version.is_none() {
+                on_done(found);
+            }
+        })
+        .collect::<Vec<_>>()
+        .await;
+
+    if let Some(imei) = imei {
+        let nonce = client.generate_nonce().await?;
+        let nonce = &nonce;
+        results = stream::iter(results)
+            .map(|mut found| async move {
+                let Some(version) = &found.version else {
+                    return found;
+                };
+                // The lookups share the FUS session, each one starts with its nonce.
+                let mut nonce = nonce.clone();
+                match client
+                    .file_info(model, imei, &found.region, version, &mut nonce)
+                    .await
+                {
+                    Ok(info) => {
+                        found.os_version = Some(info.os_version);
+                        found.size = Some(info.binary_size);
+                    }
+                    Err(e) => found.error = Some(e.to_string()),
+                }
+                found
+            })
+            .buffer_unordered(concurrency.max(1))
+            .inspect(|found| {
+                if found.version.is_some() {
+                    on_done(found);
+                }
+            })
+            .collect()
+            .await;
+    }
+
+    sort(&mut results, regions);
+    Ok(results)
+}
+
+/// Sorts the newest builds first and keeps the order of `regions` otherwise.
+fn sort(results: &mut [Found], regions: &[&str]) {
+    let position = |found: &Found| regions.iter().position(|r| *r == found.region);
+    results.sort_by_cached_key(|found| {
+        let build = found.build().map(str::to_owned);
+        (Reverse(build), position(found))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn found(region: &str, version: Option<&str>) -> Found {
+        Found {
+            region: region.to_owned(),
+            version: version.map(str::to_owned),
+            os_version: None,
+            size: None,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn newest_first() {
+        let mut results = vec![
+            found("XAA", None),
+            found("DBT", Some("G991BXXU5CVC3/G991BOXM5CVC3/G991BXXU5CVC3")),
+            found("INS", Some("G991BXXU5CVDD/G991BODM5CVDD/G991BXXU5CVDD")),
+            found("EUX", Some("G991BXXU5CVDD/G991BOXM5CVDD/G991BXXU5CVDD")),
+        ];
+        sort(&mut results, &["EUX", "DBT", "XAA", "INS"]);
+        let regions = results
+            .iter()
+            .map(|f| f.region.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(regions, ["EUX", "INS", "DBT", "XAA"]);
+        assert_eq!(results[0].build(), Some("5CVDD"));
+    }
+}
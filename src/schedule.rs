@@ -0,0 +1,292 @@
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+use std::time::Duration;
+
+use bytes::Bytes;
+use chrono::{Local, NaiveTime, Timelike};
+use futures_util::stream::{self, Stream};
+use reqwest::Response;
+
+use crate::auth::Nonce;
+use crate::binary_info::BinaryInfo;
+use crate::client::Client;
+
+/// A daily time window in local time like `22:00-06:00`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Window {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl Window {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+
+    /// Returns how long it takes from `time` until the window opens.
+    pub fn wait_time(&self, time: NaiveTime) -> Duration {
+        if self.contains(time) {
+            return Duration::ZERO;
+        }
+        let secs = |t: NaiveTime| i64::from(t.num_seconds_from_midnight());
+        let wait = (secs(self.start) - secs(time)).rem_euclid(24 * 60 * 60);
+        Duration::from_secs(wait as u64)
+    }
+}
+
+impl FromStr for Window {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s
+            .split_once('-')
+            .ok_or_else(|| format!("invalid time window `{s}`, expected HH:MM-HH:MM"))?;
+        let parse = |t: &str| {
+            NaiveTime::parse_from_str(t.trim(), "%H:%M")
+                .map_err(|_| format!("invalid time `{t}`, expected HH:MM"))
+        };
+        let (start, end) = (parse(start)?, parse(end)?);
+        if start == end {
+            return Err(format!(
+                "empty time window `{s}`, start and end are the same"
+            ));
+        }
+        Ok(Self { start, end })
+    }
+}
+
+impl fmt::Display for Window {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}-{}",
+            self.start.format("%H:%M"),
+            self.end.format("%H:%M")
+        )
+    }
+}
+
+struct State<'a, F> {
+    client: &'a Client,
+    info: &'a BinaryInfo,
+    nonce: &'a mut Nonce,
+    window: Option<Window>,
+    on_pause: F,
+    resp: Option<Response>,
+    offset: u64,
+    attempt: u32,
+}
+
+/// Streams the body of the download response and only transfers data inside the time window.
+///
+/// The connection is closed outside the window and the download continues with a
+/// range request once the window opens again. `on_pause` is called with `true`
+/// when the download is paused and with `false` when it continues. A body that
+/// fails partway through is resumed the same way, retried like the requests.
+pub fn download<'a, F>(
+    client: &'a Client,
+    info: &'a BinaryInfo,
+    nonce: &'a mut Nonce,
+    resp: Response,
+    window: Option<Window>,
+    on_pause: F,
+) -> impl Stream<Item = io::Result<Bytes>> + 'a
+where
+    F: FnMut(bool) + 'a,
+{
+    download_from(client, info, nonce, resp, 0, window, on_pause)
+}
+
+/// Like [`download`] for a response of [`Client::download_from`] that starts at `offset`.
+pub fn download_from<'a, F>(
+    client: &'a Client,
+    info: &'a BinaryInfo,
+    nonce: &'a mut Nonce,
+    resp: Response,
+    offset: u64,
+    window: Option<Window>,
+    on_pause: F,
+) -> impl Stream<Item = io::Result<Bytes>> + 'a
+where
+    F: FnMut(bool) + 'a,
+{
+    let state = State {
+        client,
+        info,
+        nonce,
+        window,
+        on_pause,
+        resp: Some(resp),
+        offset,
+        attempt: 1,
+    };
+
+    stream::try_unfold(state, |mut state| async move {
+        if let Some(window) = state.window {
+            let mut wait = window.wait_time(Local::now().time());
+            if !wait.is_zero() {
+                state.resp = None;
+                (state.on_pause)(true);
+                tracing::info!("download paused until {}", window.start.format("%H:%M"));
+                while !wait.is_zero() {
+                    // Wake up regularly in case the clock is adjusted.
+                    tokio::time::sleep(wait.min(Duration::from_secs(60))).await;
+                    wait = window.wait_time(Local::now().time());
+                }
+                (state.on_pause)(false);
+            }
+        }
+
+        loop {
+            let resp = match &mut state.resp {
+                Some(resp) => resp,
+                None => {
+                    let resp = state
+                        .client
+                        .download_from(state.info, state.nonce, state.offset)
+                        .await
+                        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+                    state.resp.insert(resp)
+                }
+            };
+
+            match resp.chunk().await {
+                Ok(chunk) => {
+                    state.attempt = 1;
+                    return Ok(chunk.map(|chunk| {
+                        state.offset += chunk.len() as u64;
+                        (chunk, state)
+                    }));
+                }
+                Err(e) => {
+                    // The connection broke, continue where it stopped.
+                    state.resp = None;
+                    state
+                        .client
+                        .recover(&mut state.attempt, e.into(), None)
+                        .await
+                        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(s: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(s, "%H:%M").unwrap()
+    }
+
+    #[test]
+    fn window_over_midnight() {
+        let window = "22:00-06:00".parse::<Window>().unwrap();
+        assert_eq!(window.to_string(), "22:00-06:00");
+
+        assert!(window.contains(time("23:00")));
+        assert!(window.contains(time("05:59")));
+        assert!(!window.contains(time("06:00")));
+        assert!(!window.contains(time("12:00")));
+
+        assert_eq!(window.wait_time(time("01:00")), Duration::ZERO);
+        assert_eq!(
+            window.wait_time(time("21:30")),
+            Duration::from_secs(30 * 60)
+        );
+        assert_eq!(
+            window.wait_time(time("06:00")),
+            Duration::from_secs(16 * 60 * 60)
+        );
+    }
+
+    #[test]
+    fn window_same_day() {
+        let window = "09:00-17:00".parse::<Window>().unwrap();
+        assert!(window.contains(time("09:00")));
+        assert!(!window.contains(time("17:00")));
+        assert_eq!(
+            window.wait_time(time("18:00")),
+            Duration::from_secs(15 * 60 * 60)
+        );
+
+        assert!("9-17".parse::<Window>().is_err());
+        assert!("09:00".parse::<Window>().is_err());
+        assert!("09:00-09:00".parse::<Window>().is_err());
+    }
+
+    #[tokio::test]
+    async fn resume_broken_body() {
+        use futures_util::TryStreamExt;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpListener;
+
+        use crate::client::RetryPolicy;
+        use crate::fus::tests::BINARY_INFORM_XML;
+        use crate::record::{self, Mode};
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let mut requests = vec![];
+            // The first response ends after half of the body.
+            for resp in [
+                "HTTP/1.1 200 OK\r\ncontent-length: 10\r\n\r\n01234",
+                "HTTP/1.1 206 Partial Content\r\ncontent-length: 5\r\n\r\n56789",
+            ] {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = [0; 1024];
+                let n = stream.read(&mut buf).await.unwrap();
+                requests.push(String::from_utf8_lossy(&buf[..n]).into_owned());
+                stream.write_all(resp.as_bytes()).await.unwrap();
+            }
+            requests
+        });
+
+        let dir = tempfile::tempdir().unwrap();
+        let status = "<FUSMsg><FUSBody><Results><Status>200</Status></Results></FUSBody></FUSMsg>";
+        for seq in 1..=2 {
+            record::tests::write(
+                dir.path(),
+                seq,
+                "NF_DownloadBinaryInitForMass.do",
+                &[],
+                status,
+            );
+        }
+        let client = Client::builder()
+            .retry(RetryPolicy {
+                max_attempts: 2,
+                initial_delay: Duration::from_millis(1),
+                max_delay: Duration::from_millis(1),
+            })
+            .record(Mode::Replay(dir.path().to_owned()))
+            .download_base_url(format!("http://{addr}/"))
+            .build()
+            .unwrap();
+
+        let info = crate::binary_info::from_xml("SM-G991B", "EUX", BINARY_INFORM_XML).unwrap();
+        let mut nonce = Nonce {
+            encoded: String::new(),
+            value: "0123456789abcdef".to_owned(),
+            signature: String::new(),
+        };
+        let resp = client.download(&info, &mut nonce).await.unwrap();
+        let body = download(&client, &info, &mut nonce, resp, None, |_| {})
+            .map_ok(|chunk| chunk.to_vec())
+            .try_concat()
+            .await
+            .unwrap();
+        assert_eq!(body, b"0123456789");
+
+        let requests = server.await.unwrap();
+        assert!(!requests[0].contains("range:"));
+        assert!(requests[1].contains("range: bytes=5-"));
+    }
+}
@@ -0,0 +1,425 @@
+//! `frigg serve`: an HTTP API for firmware lookups and downloads.
+//!
+//! The endpoints take their parameters from the query string and answer with JSON,
+//! only `/download` streams the decrypted firmware. All requests share one
+//! [`Client`] and the nonce of one FUS session, so the FUS requests are sent one
+//! after the other.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use anyhow::Context;
+use bytes::Bytes;
+use futures_util::TryStreamExt;
+use generic_array::{typenum::U16, GenericArray};
+use http::header::{AUTHORIZATION, CONTENT_DISPOSITION, CONTENT_TYPE, WWW_AUTHENTICATE};
+use http::{Method, Request, Response, StatusCode};
+use http_body_util::combinators::UnsyncBoxBody;
+use http_body_util::{BodyExt, Full, StreamBody};
+use hyper::body::Frame;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper_util::rt::TokioIo;
+use serde::Serialize;
+use tokio::net::TcpListener;
+use tokio::sync::Mutex;
+use tokio_util::io::{ReaderStream, StreamReader};
+
+use frigg::auth::Nonce;
+use frigg::binary_info::{BinaryInfo, DecryptKey};
+use frigg::client::{Client, FusStatus};
+use frigg::decrypt::DecryptReader;
+use frigg::Error;
+
+type Body = UnsyncBoxBody<Bytes, io::Error>;
+
+struct State {
+    client: Client,
+    nonce: Mutex<Nonce>,
+    /// The bearer token that requests have to send, if any.
+    token: Option<String>,
+}
+
+/// An error response with a JSON body like `{"error": "..."}`.
+#[derive(Debug)]
+struct HttpError {
+    status: StatusCode,
+    message: String,
+}
+
+impl HttpError {
+    fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+
+    fn into_response(self) -> Response<Body> {
+        let mut resp = json(self.status, &serde_json::json!({ "error": self.message }));
+        if self.status == StatusCode::UNAUTHORIZED {
+            resp.headers_mut()
+                .insert(WWW_AUTHENTICATE, "Bearer".parse().unwrap());
+        }
+        resp
+    }
+}
+
+/// Errors of the FUS and download servers. A FUS status about the request itself is
+/// passed on, everything else is a failure of the upstream servers.
+impl From<Error> for HttpError {
+    fn from(e: Error) -> Self {
+        let status = match e.downcast_ref() {
+            // FUS answers 408 for a firmware version that doesn't exist.
+            Some(FusStatus(408)) => StatusCode::NOT_FOUND,
+            Some(FusStatus(400)) => StatusCode::BAD_REQUEST,
+            _ => StatusCode::BAD_GATEWAY,
+        };
+        Self::new(status, format!("{e:#}"))
+    }
+}
+
+#[derive(Serialize)]
+struct Version<'a> {
+    model: &'a str,
+    region: &'a str,
+    version: String,
+}
+
+#[derive(Serialize)]
+struct Key<'a> {
+    model: &'a str,
+    region: &'a str,
+    version: &'a str,
+    binary_name: &'a str,
+    key: String,
+}
+
+/// Serves the API on `listen` until Ctrl-C is pressed.
+pub async fn run(client: Client, listen: SocketAddr, token: Option<String>) -> Result<(), Error> {
+    let nonce = client.generate_nonce().await?;
+    let state = Arc::new(State {
+        client,
+        nonce: Mutex::new(nonce),
+        token,
+    });
+
+    let listener = TcpListener::bind(listen)
+        .await
+        .with_context(|| format!("failed to listen on {listen}"))?;
+    println!("Listening on http://{}", listener.local_addr()?);
+
+    loop {
+        let (stream, peer) = tokio::select! {
+            accepted = listener.accept() => accepted?,
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+        };
+        let state = state.clone();
+        tokio::spawn(async move {
+            let service = service_fn(|req| handle(state.clone(), req));
+            let conn = http1::Builder::new().serve_connection(TokioIo::new(stream), service);
+            if let Err(e) = conn.await {
+                tracing::debug!(%peer, "connection failed: {e}");
+            }
+        });
+    }
+}
+
+async fn handle<B>(state: Arc<State>, req: Request<B>) -> Result<Response<Body>, Infallible> {
+    let resp = match route(&state, &req).await {
+        Ok(resp) => resp,
+        Err(e) => e.into_response(),
+    };
+    tracing::info!(
+        method = %req.method(),
+        path = req.uri().path(),
+        status = resp.status().as_u16(),
+        "request"
+    );
+    Ok(resp)
+}
+
+async fn route<B>(state: &State, req: &Request<B>) -> Result<Response<Body>, HttpError> {
+    if !state.authorized(req) {
+        return Err(HttpError::new(
+            StatusCode::UNAUTHORIZED,
+            "missing or invalid token",
+        ));
+    }
+    if req.method() != Method::GET {
+        return Err(HttpError::new(
+            StatusCode::METHOD_NOT_ALLOWED,
+            "only GET requests are supported",
+        ));
+    }
+
+    let params = Params::from_request(req);
+    match req.uri().path() {
+        "/version" => {
+            let (model, region) = (params.get("model")?, params.get("region")?);
+            let version = state.client.fetch_version(model, region).await?;
+            let version = Version {
+                model,
+                region,
+                version,
+            };
+            Ok(json(StatusCode::OK, &version))
+        }
+        "/info" => {
+            let info = state.file_info(&params).await?;
+            Ok(json(StatusCode::OK, &info))
+        }
+        "/key" => {
+            let info = state.file_info(&params).await?;
+            let (_, key) = decrypted_name(&info)?;
+            let key = Key {
+                model: params.get("model")?,
+                region: params.get("region")?,
+                version: &info.version,
+                binary_name: &info.binary_name,
+                key: format!("{key:02X}"),
+            };
+            Ok(json(StatusCode::OK, &key))
+        }
+        "/download" => {
+            let info = state.file_info(&params).await?;
+            state.download(&info).await
+        }
+        path => Err(HttpError::new(
+            StatusCode::NOT_FOUND,
+            format!("unknown endpoint {path}"),
+        )),
+    }
+}
+
+impl State {
+    fn authorized<B>(&self, req: &Request<B>) -> bool {
+        let Some(token) = &self.token else {
+            return true;
+        };
+        req.headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|sent| constant_time_eq(sent.as_bytes(), token.as_bytes()))
+    }
+
+    /// Looks up the firmware of `version` or the latest one.
+    async fn file_info(&self, params: &Params) -> Result<BinaryInfo, HttpError> {
+        let model = params.get("model")?;
+        let region = params.get("region")?;
+        let imei = params.get("imei")?;
+        let version = match params.0.get("version") {
+            Some(version) => version.clone(),
+            None => self.client.fetch_version(model, region).await?,
+        };
+
+        let mut nonce = self.nonce.lock().await;
+        let info = self
+            .client
+            .file_info(model, imei, region, &version, &mut nonce)
+            .await?;
+        Ok(info)
+    }
+
+    /// Streams the decrypted firmware while it's downloaded.
+    ///
+    /// The size after decryption isn't known up front, so the body is chunked. If the
+    /// download or decryption fails, the connection is closed without the last chunk
+    /// and clients see a truncated body instead of an error response.
+    async fn download(&self, info: &BinaryInfo) -> Result<Response<Body>, HttpError> {
+        let (filename, key) = decrypted_name(info)?;
+        let resp = {
+            let mut nonce = self.nonce.lock().await;
+            self.client.download(info, &mut nonce).await?
+        };
+
+        let stream = resp
+            .bytes_stream()
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e));
+        let reader = StreamReader::new(Box::pin(stream));
+        let reader = DecryptReader::new(key, reader).expect("16 byte key");
+        let body = StreamBody::new(ReaderStream::new(reader).map_ok(Frame::data));
+
+        let resp = Response::builder()
+            .header(CONTENT_TYPE, "application/zip")
+            .header(
+                CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{filename}\""),
+            )
+            .body(body.boxed_unsync())
+            .expect("valid response");
+        Ok(resp)
+    }
+}
+
+/// Returns the name of the decrypted firmware and its key.
+fn decrypted_name(info: &BinaryInfo) -> Result<(&str, &GenericArray<u8, U16>), HttpError> {
+    let name = &info.binary_name;
+    match (
+        &info.decrypt_key,
+        name.strip_suffix(".enc2"),
+        name.strip_suffix(".enc4"),
+    ) {
+        (DecryptKey::V2(key), Some(name), None) | (DecryptKey::V4(key), None, Some(name)) => {
+            Ok((name, key))
+        }
+        _ => Err(HttpError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("couldn't determine the decryption key of {name}"),
+        )),
+    }
+}
+
+struct Params(HashMap<String, String>);
+
+impl Params {
+    fn from_request<B>(req: &Request<B>) -> Self {
+        let mut url = reqwest::Url::parse("http://localhost/").expect("valid url");
+        url.set_query(req.uri().query());
+        Self(url.query_pairs().into_owned().collect())
+    }
+
+    fn get(&self, name: &str) -> Result<&str, HttpError> {
+        self.0
+            .get(name)
+            .map(String::as_str)
+            .filter(|value| !value.is_empty())
+            .ok_or_else(|| {
+                HttpError::new(
+                    StatusCode::BAD_REQUEST,
+                    format!("missing parameter `{name}`"),
+                )
+            })
+    }
+}
+
+fn json<T: Serialize>(status: StatusCode, value: &T) -> Response<Body> {
+    let body = serde_json::to_vec(value).expect("serializable value");
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(
+            Full::new(Bytes::from(body))
+                .map_err(|e| match e {})
+                .boxed_unsync(),
+        )
+        .expect("valid response")
+}
+
+/// Compares the tokens without leaking the position of the first difference.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use frigg::client::RetryPolicy;
+    use frigg::record::{Exchange, Mode};
+
+    fn state(token: Option<&str>) -> Arc<State> {
+        with_client(Client::new().unwrap(), token)
+    }
+
+    fn with_client(client: Client, token: Option<&str>) -> Arc<State> {
+        Arc::new(State {
+            client,
+            nonce: Mutex::new(Nonce {
+                encoded: String::new(),
+                value: String::new(),
+                signature: String::new(),
+            }),
+            token: token.map(str::to_owned),
+        })
+    }
+
+    async fn status(state: &Arc<State>, uri: &str, token: Option<&str>) -> StatusCode {
+        let mut req = Request::get(uri);
+        if let Some(token) = token {
+            req = req.header(AUTHORIZATION, format!("Bearer {token}"));
+        }
+        let resp = handle(state.clone(), req.body(()).unwrap()).await.unwrap();
+        resp.status()
+    }
+
+    #[tokio::test]
+    async fn auth_and_routes() {
+        let state = state(Some("secret"));
+        assert_eq!(
+            status(&state, "/version", None).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(&state, "/version", Some("guess")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(&state, "/version?model=SM-G991B", Some("secret")).await,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            status(&state, "/info?model=SM-G991B&region=EUX", Some("secret")).await,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            status(&state, "/firmware", Some("secret")).await,
+            StatusCode::NOT_FOUND
+        );
+
+        let open = self::state(None);
+        assert_eq!(status(&open, "/", None).await, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn fus_status() {
+        for (fus, expected) in [
+            (408, StatusCode::NOT_FOUND),
+            (400, StatusCode::BAD_REQUEST),
+            (500, StatusCode::BAD_GATEWAY),
+        ] {
+            let dir = tempfile::tempdir().unwrap();
+            let exchange = Exchange {
+                method: String::new(),
+                url: String::new(),
+                request_headers: vec![],
+                request_body: None,
+                status: 200,
+                response_headers: vec![],
+                response_body: format!(
+                    "<FUSMsg><FUSBody><Results><Status>{fus}</Status></Results></FUSBody></FUSMsg>"
+                ),
+            };
+            let path = dir.path().join("001-NF_DownloadBinaryInform.do.json");
+            std::fs::write(path, serde_json::to_vec(&exchange).unwrap()).unwrap();
+
+            let client = Client::builder()
+                .retry(RetryPolicy::none())
+                .record(Mode::Replay(dir.path().to_owned()))
+                .build()
+                .unwrap();
+            let state = with_client(client, None);
+            let uri = "/info?model=SM-G991B&region=EUX&imei=0&version=A%2FB%2FC";
+            assert_eq!(
+                status(&state, uri, None).await,
+                expected,
+                "FUS status {fus}"
+            );
+        }
+    }
+
+    #[test]
+    fn params() {
+        let req = Request::get("/info?model=SM-G991B&region=EUX&version=A%2FB%2FC")
+            .body(())
+            .unwrap();
+        let params = Params::from_request(&req);
+        assert_eq!(params.get("model").unwrap(), "SM-G991B");
+        assert_eq!(params.get("version").unwrap(), "A/B/C");
+        assert!(params.get("imei").is_err());
+    }
+}
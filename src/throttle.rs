@@ -0,0 +1,91 @@
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, ReadBuf};
+use tokio::time::{sleep, Instant, Sleep};
+
+/// Limits the reader to `rate` bytes per second.
+pub fn limit<R>(inner: R, rate: u64) -> Throttle<R>
+where
+    R: AsyncRead + Unpin,
+{
+    Throttle {
+        inner,
+        rate: rate as f64,
+        allowance: rate as f64,
+        last: Instant::now(),
+        sleep: None,
+    }
+}
+
+/// A token bucket that allows bursts of up to one second of data.
+///
+/// Reads aren't split, so a read can overdraw the allowance and the next
+/// read waits until it is paid back.
+pub struct Throttle<R> {
+    inner: R,
+    rate: f64,
+    allowance: f64,
+    last: Instant,
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl<R> AsyncRead for Throttle<R>
+where
+    R: AsyncRead + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        loop {
+            if let Some(sleep) = &mut this.sleep {
+                ready!(sleep.as_mut().poll(cx));
+                this.sleep = None;
+            }
+
+            let now = Instant::now();
+            let elapsed = now.duration_since(this.last).as_secs_f64();
+            this.allowance = (this.allowance + elapsed * this.rate).min(this.rate);
+            this.last = now;
+
+            if this.allowance >= 0.0 {
+                break;
+            }
+            let wait = Duration::from_secs_f64(-this.allowance / this.rate);
+            this.sleep = Some(Box::pin(sleep(wait)));
+        }
+
+        let filled = buf.filled().len();
+        ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+        this.allowance -= (buf.filled().len() - filled) as f64;
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::io::AsyncReadExt;
+
+    #[tokio::test(start_paused = true)]
+    async fn limit_rate() {
+        let data = vec![0; 10 * 1024];
+        let mut reader = limit(&data[..], 2048);
+
+        let start = Instant::now();
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).await.unwrap();
+
+        // The first second is covered by the initial burst.
+        assert_eq!(out.len(), data.len());
+        assert!(start.elapsed() >= Duration::from_secs(4));
+    }
+}
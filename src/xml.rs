@@ -1,6 +1,6 @@
 use roxmltree::{Document, Error, Node};
 
-pub fn parse(xml: &str) -> Result<Document, Error> {
+pub fn parse(xml: &str) -> Result<Document<'_>, Error> {
     Document::parse(xml)
 }
 
@@ -18,20 +18,10 @@ fn get_node<'a, 'input: 'a>(node: Node<'a, 'input>, path: &[&str]) -> Option<Nod
 }
 
 pub trait XmlExt {
-    fn get_elem(&self, path: &[&str]) -> Option<Node<'_, '_>>;
-
     fn get_elem_text(&self, path: &[&str]) -> Option<&str>;
 }
 
 impl<'input> XmlExt for Document<'input> {
-    fn get_elem(&self, path: &[&str]) -> Option<Node<'_, '_>> {
-        let (root, path) = path.split_first()?;
-        if self.root_element().tag_name().name() != *root {
-            return None;
-        }
-        get_node(self.root_element(), path)
-    }
-
     fn get_elem_text(&self, path: &[&str]) -> Option<&str> {
         let (root, path) = path.split_first()?;
         if self.root_element().tag_name().name() != *root {
@@ -42,10 +32,6 @@ impl<'input> XmlExt for Document<'input> {
 }
 
 impl<'a, 'input: 'a> XmlExt for Node<'a, 'input> {
-    fn get_elem(&self, path: &[&str]) -> Option<Node<'_, '_>> {
-        get_node(*self, path)
-    }
-
     fn get_elem_text(&self, path: &[&str]) -> Option<&str> {
         get_node(*self, path).and_then(|e| e.text())
     }